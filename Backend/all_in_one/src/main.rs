@@ -0,0 +1,87 @@
+use data_processor::run_processor;
+use datafeed_fetcher::run_fetcher;
+use shared::shutdown::Shutdown;
+use shared::telemetry::{start_metrics_server, Readiness};
+use shared::transport::InMemoryTransport;
+use shared::{load_config, TransportKind};
+use std::process::ExitCode;
+use tracing::dispatcher::SetGlobalDefaultError;
+use tracing::{error, info};
+
+// Runs the fetcher and processor loops side by side in one runtime, handing messages over through
+// an in-process queue so that no Redis server is needed
+#[tokio::main]
+async fn main() -> Result<ExitCode, SetGlobalDefaultError> {
+    let subscriber = tracing_subscriber::fmt()
+        .compact()
+        .json()
+        .with_file(true)
+        .with_line_number(true)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber)?;
+
+    // Set up config
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(e) => {
+            error!(error = ?e, "Configuration could not be initialized");
+            return Ok(ExitCode::FAILURE);
+        }
+    };
+
+    if config.transport != TransportKind::InMemory {
+        info!(
+            configured = ?config.transport,
+            "Ignoring configured transport, all-in-one mode always uses the in-memory queue"
+        );
+    }
+
+    // Both loops record into the same global registry, so one endpoint serves everything
+    let readiness = Readiness::new();
+    if let Some(metrics_config) = &config.metrics {
+        if let Err(e) = start_metrics_server(metrics_config, readiness.clone()).await {
+            error!(error = ?e, "Metrics server could not be started");
+            return Ok(ExitCode::FAILURE);
+        }
+    }
+
+    // Both loops stop on the same signal, and either one failing to start stops the other, as
+    // neither is any use alone. Anything still in the in-memory queue is lost either way
+    let shutdown = Shutdown::listen();
+    let transport = InMemoryTransport::new();
+    let (fetcher_result, processor_result) = tokio::join!(
+        async {
+            let result = run_fetcher(&config, Box::new(transport.clone()), shutdown.clone()).await;
+            if result.is_err() {
+                shutdown.request();
+            }
+            result
+        },
+        async {
+            let result = run_processor(
+                &config,
+                Box::new(transport.clone()),
+                readiness,
+                shutdown.clone(),
+            )
+            .await;
+            if result.is_err() {
+                shutdown.request();
+            }
+            result
+        },
+    );
+
+    let mut exit_code = ExitCode::SUCCESS;
+    if let Err(e) = fetcher_result {
+        error!(error = ?e, "Fetcher could not be started");
+        exit_code = ExitCode::FAILURE;
+    }
+    if let Err(e) = processor_result {
+        error!(error = ?e, "Processor could not be started");
+        exit_code = ExitCode::FAILURE;
+    }
+
+    Ok(exit_code)
+}
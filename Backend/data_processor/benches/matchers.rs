@@ -0,0 +1,52 @@
+// Compares the linear regex scan with the prebuilt index over a synthetic set of positions.
+// Run with `cargo bench -p data_processor`
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{controllers, positions, Lcg};
+use data_processor::matchers::{all_matches, MatcherIndex};
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ITERATIONS: u32 = 20;
+
+fn time(iterations: u32, mut f: impl FnMut() -> usize) -> Duration {
+    let start = Instant::now();
+    for _ in 0..iterations {
+        black_box(f());
+    }
+    start.elapsed() / iterations
+}
+
+fn main() {
+    let mut rng = Lcg::new(2024);
+    let index = MatcherIndex::new(positions(&mut rng, 24));
+    let controllers = controllers(&mut rng, index.positions(), 1_000);
+    println!(
+        "{} positions, {} controllers per datafeed",
+        index.positions().len(),
+        controllers.len()
+    );
+
+    let linear = time(ITERATIONS, || {
+        controllers
+            .iter()
+            .filter_map(|c| all_matches(index.positions(), c))
+            .count()
+    });
+    let indexed = time(ITERATIONS, || {
+        controllers
+            .iter()
+            .filter_map(|c| index.all_matches(c))
+            .count()
+    });
+    let build = time(ITERATIONS, || {
+        MatcherIndex::new(positions(&mut Lcg::new(2024), 24))
+            .positions()
+            .len()
+    });
+
+    println!("linear scan:   {linear:?} per datafeed");
+    println!("matcher index: {indexed:?} per datafeed");
+    println!("index build (incl. fixtures): {build:?}");
+}
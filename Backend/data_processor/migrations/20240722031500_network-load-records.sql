@@ -0,0 +1,12 @@
+create table if not exists network_load_records (
+    id integer generated always as identity primary key,
+    update timestamptz not null,
+    connected_clients int not null,
+    unique_users int not null,
+    num_pilots int not null,
+    num_controllers int not null,
+    num_atis int not null,
+    num_vnas_controllers int not null
+);
+
+create index if not exists network_load_records_update_idx on network_load_records (update);
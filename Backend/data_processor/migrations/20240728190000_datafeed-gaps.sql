@@ -0,0 +1,8 @@
+create table if not exists datafeed_gaps (
+    id uuid primary key,
+    first_skipped timestamptz not null,
+    last_skipped timestamptz not null,
+    num_skipped int not null
+);
+
+create index if not exists datafeed_gaps_first_skipped_idx on datafeed_gaps (first_skipped);
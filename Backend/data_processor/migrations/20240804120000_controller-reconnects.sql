@@ -0,0 +1,6 @@
+alter table controller_sessions add column if not exists last_logon_time timestamptz;
+alter table controller_sessions add column if not exists disconnect_count int not null default 0;
+
+update controller_sessions set last_logon_time = start_time where last_logon_time is null;
+
+alter table controller_sessions alter column last_logon_time set not null;
@@ -0,0 +1,11 @@
+create table if not exists datafeed_glitches (
+    id uuid primary key,
+    first_update timestamptz not null,
+    last_update timestamptz not null,
+    num_ticks int not null,
+    baseline_controllers real not null,
+    min_controllers int not null,
+    accepted boolean not null
+);
+
+create index if not exists datafeed_glitches_first_update_idx on datafeed_glitches (first_update);
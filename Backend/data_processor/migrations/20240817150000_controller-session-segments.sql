@@ -0,0 +1,14 @@
+create table if not exists controller_session_segments (
+    id uuid primary key,
+    controller_session_id uuid not null,
+    controller_session_is_active bool not null,
+    callsign text not null,
+    frequency text not null,
+    start_time timestamptz not null,
+    end_time timestamptz,
+    datafeed_first timestamptz not null,
+    datafeed_last timestamptz not null,
+    foreign key (controller_session_id, controller_session_is_active) references controller_sessions on update cascade
+);
+
+create index if not exists controller_session_segments_controller_session_id_idx on controller_session_segments (controller_session_id);
@@ -0,0 +1,44 @@
+-- What a position session is keyed by: its simple callsign, or the id of the vNAS position its
+-- controllers matched, depending on the configured position identity
+alter table position_sessions add column if not exists position_key text;
+
+update position_sessions set position_key = position_simple_callsign where position_key is null;
+
+alter table position_sessions alter column position_key set not null;
+
+-- Position sessions rolled up to their simple callsign. Overlapping or touching sessions with the
+-- same simple callsign, e.g. split sectors staffed at the same time, are combined into one row
+create or replace view position_callsign_sessions as
+with spans as (
+    select
+        id,
+        position_simple_callsign,
+        position_key,
+        start_time,
+        coalesce(end_time, last_updated) as end_time,
+        is_active,
+        max(coalesce(end_time, last_updated)) over (
+            partition by position_simple_callsign
+            order by start_time, id
+            rows between unbounded preceding and 1 preceding
+        ) as previous_end
+    from position_sessions
+), islands as (
+    select
+        *,
+        count(*) filter (where previous_end is null or start_time > previous_end) over (
+            partition by position_simple_callsign
+            order by start_time, id
+            rows between unbounded preceding and current row
+        ) as island
+    from spans
+)
+select
+    position_simple_callsign,
+    min(start_time) as start_time,
+    max(end_time) as end_time,
+    bool_or(is_active) as is_active,
+    count(*) as num_position_sessions,
+    count(distinct position_key) as num_positions
+from islands
+group by position_simple_callsign, island;
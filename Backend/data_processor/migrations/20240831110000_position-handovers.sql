@@ -0,0 +1,17 @@
+-- One controller taking over a position from another. Overlap is negative when the position was
+-- unstaffed in between
+create table if not exists position_handovers (
+    id uuid primary key,
+    position_key text not null,
+    position_simple_callsign text not null,
+    outgoing_controller_session_id uuid not null,
+    outgoing_cid int not null,
+    outgoing_end_time timestamptz not null,
+    incoming_controller_session_id uuid not null,
+    incoming_cid int not null,
+    incoming_start_time timestamptz not null,
+    overlap interval not null
+);
+
+create index if not exists position_handovers_outgoing_end_time_idx on position_handovers (outgoing_end_time);
+create index if not exists position_handovers_position_key_idx on position_handovers (position_key);
@@ -0,0 +1,14 @@
+-- Controllers online each day who matched no vNAS position, or more than one, counted once per
+-- session for every day and frequency they were seen on. For no match, the candidates are the
+-- positions sharing the controller's simple callsign
+create table if not exists unmatched_controllers (
+    day date not null,
+    callsign text not null,
+    frequency text not null,
+    num_matches int not null,
+    candidate_positions jsonb not null,
+    num_sessions int not null,
+    first_seen timestamptz not null,
+    last_seen timestamptz not null,
+    primary key (day, callsign, frequency)
+);
@@ -0,0 +1,2 @@
+-- Looked up on startup to find positions still waiting for someone to take over
+create index if not exists position_handovers_outgoing_controller_session_id_idx on position_handovers (outgoing_controller_session_id);
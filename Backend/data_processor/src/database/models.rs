@@ -2,6 +2,7 @@ use crate::interval_from;
 use crate::vnas::extended_models::PositionExt;
 use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
 use std::cmp::{max, min};
 use tracing::info;
 use uuid::Uuid;
@@ -9,9 +10,7 @@ use vatsim_utils::models::Controller;
 
 #[derive(Debug, sqlx::FromRow)]
 pub struct VnasFetchRecord {
-    pub id: i32,
     pub update_time: DateTime<Utc>,
-    pub success: bool,
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -20,6 +19,83 @@ pub struct Artcc {
     pub last_updated: DateTime<Utc>,
 }
 
+#[derive(Debug)]
+pub struct NetworkLoadRecord {
+    pub update: DateTime<Utc>,
+    pub connected_clients: i32,
+    pub unique_users: i32,
+    pub num_pilots: i32,
+    pub num_controllers: i32,
+    pub num_atis: i32,
+    pub num_vnas_controllers: i32,
+}
+
+// How many sessions were tracked in a tick
+#[derive(Debug, Clone)]
+pub struct DatafeedRecord {
+    pub update: DateTime<Utc>,
+    pub num_tracked_controller_sessions: i32,
+    pub num_tracked_position_sessions: i32,
+}
+
+// A run of consecutive stale messages that were skipped instead of processed
+#[derive(Debug)]
+pub struct DatafeedGap {
+    pub id: Uuid,
+    pub first_skipped: DateTime<Utc>,
+    pub last_skipped: DateTime<Utc>,
+    pub num_skipped: i32,
+}
+
+impl DatafeedGap {
+    pub fn starting_at(update: DateTime<Utc>) -> DatafeedGap {
+        DatafeedGap {
+            id: Uuid::now_v7(),
+            first_skipped: update,
+            last_skipped: update,
+            num_skipped: 0,
+        }
+    }
+
+    pub fn extend_to(&mut self, update: DateTime<Utc>) {
+        self.last_skipped = max(self.last_skipped, update);
+        self.num_skipped += 1;
+    }
+}
+
+// A run of consecutive ticks with suspiciously few vNAS controllers. Accepted if the count was still
+// down once the hold ran out and the missing sessions were ended after all
+#[derive(Debug, Clone)]
+pub struct DatafeedGlitch {
+    pub id: Uuid,
+    pub first_update: DateTime<Utc>,
+    pub last_update: DateTime<Utc>,
+    pub num_ticks: i32,
+    pub baseline_controllers: f32,
+    pub min_controllers: i32,
+    pub accepted: bool,
+}
+
+impl DatafeedGlitch {
+    pub fn starting_at(update: DateTime<Utc>, baseline_controllers: f64) -> DatafeedGlitch {
+        DatafeedGlitch {
+            id: Uuid::now_v7(),
+            first_update: update,
+            last_update: update,
+            num_ticks: 0,
+            baseline_controllers: baseline_controllers as f32,
+            min_controllers: i32::MAX,
+            accepted: false,
+        }
+    }
+
+    pub fn extend_to(&mut self, update: DateTime<Utc>, num_controllers: usize) {
+        self.last_update = max(self.last_update, update);
+        self.num_ticks += 1;
+        self.min_controllers = min(self.min_controllers, num_controllers as i32);
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct VnasPositionInfo {
     pub id: String,
@@ -63,22 +139,28 @@ pub struct ControllerSession {
     pub position_session_id: Uuid,
     pub position_session_is_active: bool,
     pub is_cooling_down: bool,
+    // Logon time of the connection the session currently follows, which moves on each time the
+    // controller reconnects within the cooldown
+    pub last_logon_time: DateTime<Utc>,
+    pub disconnect_count: i32,
 }
 
 impl ControllerSession {
+    // Cooldown is measured against the datafeed timestamp rather than the clock, so replayed
+    // datafeeds end sessions exactly as they were ended live
     pub fn end_session(
         &mut self,
         end_time: Option<DateTime<Utc>>,
-        datafeed_iteration_timestamp: Option<DateTime<Utc>>,
+        datafeed_timestamp: DateTime<Utc>,
+        cooldown: Duration,
     ) {
         if self.is_active {
             if !self.is_cooling_down {
                 self.end_time = end_time.or(Some(self.last_updated));
             }
 
-            let current_time = datafeed_iteration_timestamp.unwrap_or(Utc::now());
-            let cooldown_end = self.end_time.expect("None time") + Duration::minutes(5);
-            if current_time < cooldown_end {
+            let cooldown_end = self.end_time.expect("None time") + cooldown;
+            if datafeed_timestamp < cooldown_end {
                 self.is_active = true;
                 self.is_cooling_down = true
             } else {
@@ -110,6 +192,139 @@ impl ControllerSession {
         self.datafeed_last = datafeed_update;
         self.duration = interval_from(self.start_time, self.last_updated)
     }
+
+    // The controller logged on again under a new logon time, e.g. after their client crashed,
+    // before this session completed. The session carries on with the new connection
+    pub fn reconnect_from(&mut self, c: &Controller, datafeed_update: DateTime<Utc>) {
+        if let Ok(d) = DateTime::parse_from_rfc3339(c.logon_time.as_str()) {
+            self.last_logon_time = d.to_utc();
+        }
+        self.disconnect_count += 1;
+        info!(
+            cid = self.cid,
+            disconnect_count = self.disconnect_count,
+            "Merging controller reconnect into existing session"
+        );
+
+        // Clears the end time if the session had already started cooling down
+        self.mark_active_from(c, datafeed_update);
+    }
+}
+
+// A continuous stretch of a controller session on one frequency and callsign. The first starts with
+// the session, later ones at the datafeed update where the change was seen
+#[derive(Debug, sqlx::FromRow, Clone)]
+pub struct ControllerSessionSegment {
+    pub id: Uuid,
+    pub controller_session_id: Uuid,
+    pub callsign: String,
+    pub frequency: String,
+    pub start_time: DateTime<Utc>,
+    pub end_time: Option<DateTime<Utc>>,
+    pub datafeed_first: DateTime<Utc>,
+    pub datafeed_last: DateTime<Utc>,
+}
+
+impl ControllerSessionSegment {
+    pub fn first_of(session: &ControllerSession) -> ControllerSessionSegment {
+        ControllerSessionSegment {
+            id: Uuid::now_v7(),
+            controller_session_id: session.id,
+            callsign: session.connected_callsign.to_owned(),
+            frequency: session.connected_frequency.to_owned(),
+            start_time: session.start_time,
+            end_time: None,
+            datafeed_first: session.datafeed_first,
+            datafeed_last: session.datafeed_last,
+        }
+    }
+
+    pub fn starting_at(
+        session: &ControllerSession,
+        c: &Controller,
+        datafeed_update: DateTime<Utc>,
+    ) -> ControllerSessionSegment {
+        ControllerSessionSegment {
+            id: Uuid::now_v7(),
+            controller_session_id: session.id,
+            callsign: c.callsign.to_owned(),
+            frequency: c.frequency.to_owned(),
+            start_time: datafeed_update,
+            end_time: None,
+            datafeed_first: datafeed_update,
+            datafeed_last: datafeed_update,
+        }
+    }
+}
+
+// One controller taking over a position from another, found once the outgoing controller's
+// session has completed. The incoming controller is whoever logged on to the position next
+#[derive(Debug, Clone)]
+pub struct PositionHandover {
+    pub id: Uuid,
+    pub position_key: String,
+    pub position_simple_callsign: String,
+    pub outgoing_controller_session_id: Uuid,
+    pub outgoing_cid: i32,
+    pub outgoing_end_time: DateTime<Utc>,
+    pub incoming_controller_session_id: Uuid,
+    pub incoming_cid: i32,
+    pub incoming_start_time: DateTime<Utc>,
+    // Negative when the position was unstaffed in between
+    pub overlap: sqlx::postgres::types::PgInterval,
+}
+
+impl PositionHandover {
+    pub fn between(
+        position_key: &str,
+        outgoing: &ControllerSession,
+        incoming: &ControllerSession,
+    ) -> PositionHandover {
+        let outgoing_end_time = outgoing.end_time.unwrap_or(outgoing.last_updated);
+        PositionHandover {
+            id: Uuid::now_v7(),
+            position_key: position_key.to_owned(),
+            position_simple_callsign: outgoing.position_simple_callsign.to_owned(),
+            outgoing_controller_session_id: outgoing.id,
+            outgoing_cid: outgoing.cid,
+            outgoing_end_time,
+            incoming_controller_session_id: incoming.id,
+            incoming_cid: incoming.cid,
+            incoming_start_time: incoming.start_time,
+            overlap: interval_from(incoming.start_time, outgoing_end_time),
+        }
+    }
+}
+
+// The completed controller session that left a position unstaffed, and nobody has taken over from
+#[derive(Debug, sqlx::FromRow)]
+pub struct PositionDeparture {
+    pub position_key: String,
+    #[sqlx(flatten)]
+    pub controller_session: ControllerSession,
+}
+
+// A controller seen for the first time in a day, or on a new frequency, who matched no vNAS
+// position, or more than one
+#[derive(Debug, Clone)]
+pub struct UnmatchedController {
+    pub callsign: String,
+    pub frequency: String,
+    pub num_matches: i32,
+    pub candidate_positions: Vec<VnasPositionInfo>,
+    pub seen: DateTime<Utc>,
+}
+
+// One callsign and frequency pair from `unmatched_controllers`, summed up over a number of days
+#[derive(Debug, sqlx::FromRow)]
+pub struct UnmatchedControllerSummary {
+    pub callsign: String,
+    pub frequency: String,
+    pub num_matches: i32,
+    pub num_sessions: i64,
+    pub num_days: i64,
+    pub last_seen: DateTime<Utc>,
+    pub candidate_positions: Json<Vec<VnasPositionInfo>>,
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -139,22 +354,25 @@ pub struct PositionSession {
     pub is_active: bool,
     pub position_simple_callsign: String,
     pub is_cooling_down: bool,
+    // What the session is tracked by, see `PositionIdentity`
+    pub position_key: String,
 }
 
 impl PositionSession {
+    // See `ControllerSession::end_session`
     pub fn end_session(
         &mut self,
         end_time: Option<DateTime<Utc>>,
-        datafeed_iteration_timestamp: Option<DateTime<Utc>>,
+        datafeed_timestamp: DateTime<Utc>,
+        cooldown: Duration,
     ) {
         if self.is_active {
             if !self.is_cooling_down {
                 self.end_time = end_time.or(Some(self.last_updated));
             }
 
-            let current_time = datafeed_iteration_timestamp.unwrap_or(Utc::now());
-            let cooldown_end = self.end_time.expect("None time") + Duration::minutes(5);
-            if current_time < cooldown_end {
+            let cooldown_end = self.end_time.expect("None time") + cooldown;
+            if datafeed_timestamp < cooldown_end {
                 self.is_active = true;
                 self.is_cooling_down = true
             } else {
@@ -189,4 +407,22 @@ impl PositionSession {
         self.datafeed_last = datafeed_update;
         self.duration = interval_from(self.start_time, self.last_updated)
     }
+
+    // Takes over the time span of another open session for the same position that is being
+    // merged into this one
+    pub fn absorb(&mut self, other: &PositionSession) {
+        self.start_time = min(self.start_time, other.start_time);
+        self.datafeed_first = min(self.datafeed_first, other.datafeed_first);
+        self.last_updated = max(self.last_updated, other.last_updated);
+        self.datafeed_last = max(self.datafeed_last, other.datafeed_last);
+        self.duration = interval_from(self.start_time, self.end_time.unwrap_or(self.last_updated));
+    }
+
+    // Completes a session that was merged into another one, skipping any cooldown
+    pub fn end_as_merged(&mut self) {
+        self.end_time = self.end_time.or(Some(self.last_updated));
+        self.is_active = false;
+        self.is_cooling_down = false;
+        self.duration = interval_from(self.start_time, self.end_time.expect("None time"));
+    }
 }
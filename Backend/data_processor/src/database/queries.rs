@@ -1,158 +1,235 @@
-use super::models::{Artcc, ControllerSession, PositionSession, VnasFetchRecord};
+use super::models::{
+    Artcc, ControllerSession, ControllerSessionSegment, DatafeedGap, DatafeedGlitch,
+    DatafeedRecord, NetworkLoadRecord, PositionDeparture, PositionHandover, PositionSession,
+    UnmatchedController, UnmatchedControllerSummary, VnasFacilityInfo, VnasFetchRecord,
+    VnasPositionInfo,
+};
 use crate::session_trackers::ActiveSessionTrackerSource::NewlyCreated;
 use crate::session_trackers::{ControllerSessionTracker, PositionSessionTracker};
 use crate::vnas::api_dtos::ArtccRoot;
 use crate::vnas::extended_models::{Callsign, FacilityWithTreeInfo, PositionExt};
-use chrono::{DateTime, Utc};
+use chrono::{NaiveDate, Utc};
 use sqlx::postgres::PgQueryResult;
 use sqlx::types::Json;
-use sqlx::{Error, Pool, Postgres};
+use sqlx::{Error, PgConnection, Pool, Postgres};
+use uuid::Uuid;
 
-pub async fn db_update_position_session(
-    pool: &Pool<Postgres>,
-    p: &PositionSessionTracker,
-) -> Result<PgQueryResult, Error> {
-    if p.marked_active {
-        let res = sqlx::query(
-        r"
-            insert into position_sessions (id, start_time, end_time, last_updated, duration, datafeed_first, datafeed_last, is_active, position_simple_callsign, is_cooling_down)
-            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+// Writes position sessions in at most three statements. Newly created sessions are inserted along
+// with their facility join rows, and the rest are updated in place, which moves them between the
+// active and completed partitions as needed
+pub async fn db_save_position_sessions(
+    conn: &mut PgConnection,
+    trackers: &[&PositionSessionTracker],
+) -> Result<(), Error> {
+    let (created, existing): (Vec<&PositionSessionTracker>, Vec<_>) = trackers
+        .iter()
+        .copied()
+        .partition(|p| p.source == NewlyCreated);
+
+    if !created.is_empty() {
+        let sessions: Vec<&PositionSession> = created.iter().map(|p| &p.position_session).collect();
+        sqlx::query(
+            r"
+            insert into position_sessions (id, start_time, end_time, last_updated, duration, datafeed_first, datafeed_last, is_active, position_simple_callsign, is_cooling_down, position_key)
+            select * from unnest($1::uuid[], $2::timestamptz[], $3::timestamptz[], $4::timestamptz[], $5::interval[], $6::timestamptz[], $7::timestamptz[], $8::bool[], $9::text[], $10::bool[], $11::text[])
             on conflict (id, is_active) do update set
                 start_time = excluded.start_time,
                 end_time = excluded.end_time,
                 last_updated = excluded.last_updated,
                 duration = excluded.duration,
                 datafeed_last = excluded.datafeed_last,
-                is_cooling_down = excluded.is_cooling_down;"
+                is_cooling_down = excluded.is_cooling_down;",
         )
-            .bind(p.position_session.id)
-            .bind(p.position_session.start_time)
-            .bind(p.position_session.end_time)
-            .bind(p.position_session.last_updated)
-            .bind(&p.position_session.duration)
-            .bind(p.position_session.datafeed_first)
-            .bind(p.position_session.datafeed_last)
-            .bind(p.position_session.is_active)
-            .bind(&p.position_session.position_simple_callsign)
-            .bind(p.position_session.is_cooling_down)
-            .execute(pool)
-            .await;
-
-        if p.source == NewlyCreated {
-            if let Some(facilities) = &p.assoc_vnas_facilities {
-                for f in facilities {
-                    sqlx::query(r"
-                        insert into position_session_facility_join (position_session_id, position_session_is_active, facility_id, frozen_data)
-                        values ($1, $2, $3, $4);")
-                        .bind(p.position_session.id)
-                        .bind(p.position_session.is_active)
-                        .bind(&f.id)
-                        .bind(Json(f))
-                        .execute(pool)
-                        .await?;
-                }
-            }
-        }
-
-        res
-    } else {
+        .bind(sessions.iter().map(|s| s.id).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.start_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.end_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.last_updated).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.duration.clone()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.datafeed_first).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.datafeed_last).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_active).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.position_simple_callsign.as_str()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_cooling_down).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.position_key.as_str()).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    if !existing.is_empty() {
+        let sessions: Vec<&PositionSession> =
+            existing.iter().map(|p| &p.position_session).collect();
         sqlx::query(
             r"
             update position_sessions set
-                is_active = $2,
-                end_time = $3,
-                last_updated = $4,
-                duration = $5,
-                datafeed_last = $6,
-                is_cooling_down = $7
-            where id = $1;",
+                is_active = u.is_active,
+                start_time = u.start_time,
+                end_time = u.end_time,
+                last_updated = u.last_updated,
+                duration = u.duration,
+                datafeed_last = u.datafeed_last,
+                is_cooling_down = u.is_cooling_down
+            from unnest($1::uuid[], $2::bool[], $3::timestamptz[], $4::timestamptz[], $5::timestamptz[], $6::interval[], $7::timestamptz[], $8::bool[])
+                as u (id, is_active, start_time, end_time, last_updated, duration, datafeed_last, is_cooling_down)
+            where position_sessions.id = u.id;",
         )
-        .bind(p.position_session.id)
-        .bind(p.position_session.is_active)
-        .bind(p.position_session.end_time)
-        .bind(p.position_session.last_updated)
-        .bind(&p.position_session.duration)
-        .bind(p.position_session.datafeed_last)
-        .bind(p.position_session.is_cooling_down)
-        .execute(pool)
-        .await
+        .bind(sessions.iter().map(|s| s.id).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_active).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.start_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.end_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.last_updated).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.duration.clone()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.datafeed_last).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_cooling_down).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    let joins: Vec<(&PositionSession, &VnasFacilityInfo)> = created
+        .iter()
+        .flat_map(|p| {
+            p.assoc_vnas_facilities
+                .iter()
+                .flatten()
+                .map(|f| (&p.position_session, f))
+        })
+        .collect();
+
+    if !joins.is_empty() {
+        sqlx::query(
+            r"
+            insert into position_session_facility_join (position_session_id, position_session_is_active, facility_id, frozen_data)
+            select * from unnest($1::uuid[], $2::bool[], $3::text[], $4::jsonb[]);",
+        )
+        .bind(joins.iter().map(|(s, _)| s.id).collect::<Vec<_>>())
+        .bind(joins.iter().map(|(s, _)| s.is_active).collect::<Vec<_>>())
+        .bind(joins.iter().map(|(_, f)| f.id.as_str()).collect::<Vec<_>>())
+        .bind(joins.iter().map(|(_, f)| Json(*f)).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
     }
+
+    Ok(())
 }
 
-pub async fn db_update_controller_session(
-    pool: &Pool<Postgres>,
-    c: &ControllerSessionTracker,
-) -> Result<PgQueryResult, Error> {
-    if c.marked_active {
-        let res = sqlx::query(
+// Same as `db_save_position_sessions`, for controller sessions and their position join rows
+pub async fn db_save_controller_sessions(
+    conn: &mut PgConnection,
+    trackers: &[&ControllerSessionTracker],
+) -> Result<(), Error> {
+    let (created, existing): (Vec<&ControllerSessionTracker>, Vec<_>) = trackers
+        .iter()
+        .copied()
+        .partition(|c| c.source == NewlyCreated);
+
+    if !created.is_empty() {
+        let sessions: Vec<&ControllerSession> =
+            created.iter().map(|c| &c.controller_session).collect();
+        // The position session may have completed since it was attached, when several ticks are
+        // saved at once, so its state is taken from the row written just before
+        sqlx::query(
             r"
-        insert into controller_sessions (id, start_time, end_time, last_updated, duration, datafeed_first, datafeed_last, is_active, cid, position_simple_callsign, connected_callsign, connected_frequency, position_session_id, position_session_is_active, is_cooling_down)
-        values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
-        on conflict (id, is_active) do update set
-            end_time = excluded.end_time,
-            last_updated = excluded.last_updated,
-            duration = excluded.duration,
-            datafeed_last = excluded.datafeed_last,
-            is_cooling_down = excluded.is_cooling_down;
-        ")
-            .bind(c.controller_session.id)
-            .bind(c.controller_session.start_time)
-            .bind(c.controller_session.end_time)
-            .bind(c.controller_session.last_updated)
-            .bind(&c.controller_session.duration)
-            .bind(c.controller_session.datafeed_first)
-            .bind(c.controller_session.datafeed_last)
-            .bind(c.controller_session.is_active)
-            .bind(c.controller_session.cid)
-            .bind(&c.controller_session.position_simple_callsign)
-            .bind(&c.controller_session.connected_callsign)
-            .bind(&c.controller_session.connected_frequency)
-            .bind(c.controller_session.position_session_id)
-            .bind(c.controller_session.position_session_is_active)
-            .bind(c.controller_session.is_cooling_down)
-            .execute(pool)
-            .await;
-
-        if c.source == NewlyCreated {
-            if let Some(positions) = &c.assoc_vnas_positions {
-                for p in positions {
-                    sqlx::query(r"
-                        insert into controller_session_position_join (controller_session_id, controller_session_is_active, position_id, position_parent_facility_id, frozen_data)
-                        values ($1, $2, $3, $4, $5);")
-                        .bind(c.controller_session.id)
-                        .bind(c.controller_session.is_active)
-                        .bind(&p.id)
-                        .bind(&p.parent_facility_id)
-                        .bind(Json(p))
-                        .execute(pool)
-                        .await?;
-                }
-            }
-        }
-
-        res
-    } else {
+            insert into controller_sessions (id, start_time, end_time, last_updated, duration, datafeed_first, datafeed_last, is_active, cid, position_simple_callsign, connected_callsign, connected_frequency, position_session_id, position_session_is_active, is_cooling_down, last_logon_time, disconnect_count)
+            select u.id, u.start_time, u.end_time, u.last_updated, u.duration, u.datafeed_first, u.datafeed_last, u.is_active, u.cid, u.position_simple_callsign, u.connected_callsign, u.connected_frequency, p.id, p.is_active, u.is_cooling_down, u.last_logon_time, u.disconnect_count
+            from unnest($1::uuid[], $2::timestamptz[], $3::timestamptz[], $4::timestamptz[], $5::interval[], $6::timestamptz[], $7::timestamptz[], $8::bool[], $9::int[], $10::text[], $11::text[], $12::text[], $13::uuid[], $14::bool[], $15::timestamptz[], $16::int[])
+                as u (id, start_time, end_time, last_updated, duration, datafeed_first, datafeed_last, is_active, cid, position_simple_callsign, connected_callsign, connected_frequency, position_session_id, is_cooling_down, last_logon_time, disconnect_count)
+                join position_sessions p on p.id = u.position_session_id
+            on conflict (id, is_active) do update set
+                end_time = excluded.end_time,
+                last_updated = excluded.last_updated,
+                duration = excluded.duration,
+                datafeed_last = excluded.datafeed_last,
+                is_cooling_down = excluded.is_cooling_down,
+                last_logon_time = excluded.last_logon_time,
+                disconnect_count = excluded.disconnect_count;",
+        )
+        .bind(sessions.iter().map(|s| s.id).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.start_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.end_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.last_updated).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.duration.clone()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.datafeed_first).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.datafeed_last).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_active).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.cid).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.position_simple_callsign.as_str()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.connected_callsign.as_str()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.connected_frequency.as_str()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.position_session_id).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_cooling_down).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.last_logon_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.disconnect_count).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    if !existing.is_empty() {
+        let sessions: Vec<&ControllerSession> =
+            existing.iter().map(|c| &c.controller_session).collect();
         sqlx::query(
             r"
-        update controller_sessions set
-            is_active = $2,
-            end_time = $3,
-            last_updated = $4,
-            duration = $5,
-            datafeed_last = $6,
-            is_cooling_down = $7
-        where id = $1;",
+            update controller_sessions set
+                is_active = u.is_active,
+                end_time = u.end_time,
+                last_updated = u.last_updated,
+                duration = u.duration,
+                datafeed_last = u.datafeed_last,
+                is_cooling_down = u.is_cooling_down,
+                last_logon_time = u.last_logon_time,
+                disconnect_count = u.disconnect_count,
+                position_session_id = p.id,
+                position_session_is_active = p.is_active
+            from unnest($1::uuid[], $2::bool[], $3::timestamptz[], $4::timestamptz[], $5::interval[], $6::timestamptz[], $7::bool[], $8::timestamptz[], $9::int[], $10::uuid[])
+                as u (id, is_active, end_time, last_updated, duration, datafeed_last, is_cooling_down, last_logon_time, disconnect_count, position_session_id)
+                join position_sessions p on p.id = u.position_session_id
+            where controller_sessions.id = u.id;",
         )
-        .bind(c.controller_session.id)
-        .bind(c.controller_session.is_active)
-        .bind(c.controller_session.end_time)
-        .bind(c.controller_session.last_updated)
-        .bind(&c.controller_session.duration)
-        .bind(c.controller_session.datafeed_last)
-        .bind(c.controller_session.is_cooling_down)
-        .execute(pool)
-        .await
+        .bind(sessions.iter().map(|s| s.id).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_active).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.end_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.last_updated).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.duration.clone()).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.datafeed_last).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.is_cooling_down).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.last_logon_time).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.disconnect_count).collect::<Vec<_>>())
+        .bind(sessions.iter().map(|s| s.position_session_id).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
+    }
+
+    // Positions matched when the session was created, plus any matched since on a new frequency
+    let joins: Vec<(&ControllerSession, &VnasPositionInfo)> = created
+        .iter()
+        .flat_map(|c| {
+            c.assoc_vnas_positions
+                .iter()
+                .flatten()
+                .map(|p| (&c.controller_session, p))
+        })
+        .chain(trackers.iter().flat_map(|c| {
+            c.added_vnas_positions
+                .iter()
+                .map(|p| (&c.controller_session, p))
+        }))
+        .collect();
+
+    if !joins.is_empty() {
+        sqlx::query(
+            r"
+            insert into controller_session_position_join (controller_session_id, controller_session_is_active, position_id, position_parent_facility_id, frozen_data)
+            select * from unnest($1::uuid[], $2::bool[], $3::text[], $4::text[], $5::jsonb[])
+            on conflict do nothing;",
+        )
+        .bind(joins.iter().map(|(s, _)| s.id).collect::<Vec<_>>())
+        .bind(joins.iter().map(|(s, _)| s.is_active).collect::<Vec<_>>())
+        .bind(joins.iter().map(|(_, p)| p.id.as_str()).collect::<Vec<_>>())
+        .bind(joins.iter().map(|(_, p)| p.parent_facility_id.as_str()).collect::<Vec<_>>())
+        .bind(joins.iter().map(|(_, p)| Json(*p)).collect::<Vec<_>>())
+        .execute(&mut *conn)
+        .await?;
     }
+
+    Ok(())
 }
 
 pub async fn db_update_vnas_position(
@@ -211,9 +288,9 @@ pub async fn db_update_vnas_facility(
     )
     .bind(&f.facility.id)
     .bind(&f.facility.name)
-    .bind(&f.facility.type_field.to_string())
+    .bind(f.facility.type_field.to_string())
     .bind(f.artcc_root.last_updated_at)
-    .bind(&f.parent_facility.as_ref().map(|p| p.id.clone()))
+    .bind(f.parent_facility.as_ref().map(|p| p.id.clone()))
     .bind(&f.artcc_root.id)
     .execute(pool)
     .await
@@ -247,6 +324,59 @@ pub async fn db_get_active_controller_sessions(
     .await
 }
 
+// Writes the open segment of each controller session along with any ended since the last save
+pub async fn db_save_controller_session_segments(
+    conn: &mut PgConnection,
+    trackers: &[&ControllerSessionTracker],
+) -> Result<(), Error> {
+    let segments: Vec<(&ControllerSessionSegment, bool)> = trackers
+        .iter()
+        .flat_map(|c| {
+            c.ended_segments
+                .iter()
+                .chain(c.segment.iter())
+                .map(|s| (s, c.controller_session.is_active))
+        })
+        .collect();
+
+    if segments.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r"
+        insert into controller_session_segments (id, controller_session_id, controller_session_is_active, callsign, frequency, start_time, end_time, datafeed_first, datafeed_last)
+        select * from unnest($1::uuid[], $2::uuid[], $3::bool[], $4::text[], $5::text[], $6::timestamptz[], $7::timestamptz[], $8::timestamptz[], $9::timestamptz[])
+        on conflict (id) do update set
+            controller_session_is_active = excluded.controller_session_is_active,
+            end_time = excluded.end_time,
+            datafeed_last = excluded.datafeed_last;",
+    )
+    .bind(segments.iter().map(|(s, _)| s.id).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(s, _)| s.controller_session_id).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(_, is_active)| *is_active).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(s, _)| s.callsign.as_str()).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(s, _)| s.frequency.as_str()).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(s, _)| s.start_time).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(s, _)| s.end_time).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(s, _)| s.datafeed_first).collect::<Vec<_>>())
+    .bind(segments.iter().map(|(s, _)| s.datafeed_last).collect::<Vec<_>>())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn db_get_open_controller_session_segments(
+    pool: &Pool<Postgres>,
+) -> Result<Vec<ControllerSessionSegment>, Error> {
+    sqlx::query_as::<_, ControllerSessionSegment>(
+        "select id, controller_session_id, callsign, frequency, start_time, end_time, datafeed_first, datafeed_last from controller_session_segments where controller_session_is_active = true and end_time is null;",
+    )
+    .fetch_all(pool)
+    .await
+}
+
 pub async fn db_get_cooldown_controller_sessions(
     pool: &Pool<Postgres>,
 ) -> Result<Vec<ControllerSession>, Error> {
@@ -277,6 +407,33 @@ pub async fn db_get_cooldown_position_sessions(
     .await
 }
 
+// The last controller session to complete on each position key, where nobody has taken over from
+// it yet and nobody is on the position now
+pub async fn db_get_last_departures(
+    pool: &Pool<Postgres>,
+) -> Result<Vec<PositionDeparture>, Error> {
+    sqlx::query_as::<_, PositionDeparture>(
+        r"
+        select * from (
+            select distinct on (p.position_key) p.position_key, c.*
+            from completed_controller_sessions c
+                join position_sessions p on p.id = c.position_session_id
+            where not c.is_cooling_down
+            order by p.position_key, c.end_time desc
+        ) last
+        where not exists (
+            select 1 from position_handovers h where h.outgoing_controller_session_id = last.id
+        ) and not exists (
+            select 1 from controller_sessions n
+                join position_sessions np on np.id = n.position_session_id
+            where np.position_key = last.position_key
+                and (n.is_active or n.start_time > last.end_time)
+        );",
+    )
+    .fetch_all(pool)
+    .await
+}
+
 pub async fn db_insert_vnas_fetch_record(
     pool: &Pool<Postgres>,
     success: bool,
@@ -291,7 +448,7 @@ pub async fn db_insert_vnas_fetch_record(
 pub async fn db_get_latest_fetch_record(
     pool: &Pool<Postgres>,
 ) -> Result<Option<VnasFetchRecord>, Error> {
-    sqlx::query_as::<_, VnasFetchRecord>("select id, update_time, success from vnas_fetch_records where success = true order by update_time desc;")
+    sqlx::query_as::<_, VnasFetchRecord>("select update_time from vnas_fetch_records where success = true order by update_time desc;")
         .fetch_optional(pool)
         .await
 }
@@ -302,16 +459,209 @@ pub async fn db_get_all_artccs(pool: &Pool<Postgres>) -> Result<Vec<Artcc>, Erro
         .await
 }
 
-pub async fn db_insert_datafeed_record(
+// Moves controller sessions off position sessions that were merged away, given as (duplicate id,
+// survivor id) pairs, and onto the sessions they were merged into
+pub async fn db_repoint_controller_sessions(
+    conn: &mut PgConnection,
+    merges: &[(Uuid, Uuid)],
+) -> Result<(), Error> {
+    if merges.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r"
+        update controller_sessions set
+            position_session_id = p.id,
+            position_session_is_active = p.is_active
+        from unnest($1::uuid[], $2::uuid[]) as m (duplicate_id, survivor_id)
+            join position_sessions p on p.id = m.survivor_id
+        where controller_sessions.position_session_id = m.duplicate_id;",
+    )
+    .bind(
+        merges
+            .iter()
+            .map(|(duplicate, _)| *duplicate)
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        merges
+            .iter()
+            .map(|(_, survivor)| *survivor)
+            .collect::<Vec<_>>(),
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn db_insert_position_handovers(
+    conn: &mut PgConnection,
+    handovers: &[PositionHandover],
+) -> Result<(), Error> {
+    if handovers.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r"
+        insert into position_handovers (id, position_key, position_simple_callsign, outgoing_controller_session_id, outgoing_cid, outgoing_end_time, incoming_controller_session_id, incoming_cid, incoming_start_time, overlap)
+        select * from unnest($1::uuid[], $2::text[], $3::text[], $4::uuid[], $5::int[], $6::timestamptz[], $7::uuid[], $8::int[], $9::timestamptz[], $10::interval[])
+        on conflict (id) do nothing;",
+    )
+    .bind(handovers.iter().map(|h| h.id).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.position_key.as_str()).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.position_simple_callsign.as_str()).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.outgoing_controller_session_id).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.outgoing_cid).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.outgoing_end_time).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.incoming_controller_session_id).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.incoming_cid).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.incoming_start_time).collect::<Vec<_>>())
+    .bind(handovers.iter().map(|h| h.overlap.clone()).collect::<Vec<_>>())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+// Adds to the per-day counts. Several sessions for the same callsign and frequency in one tick are
+// summed up first, as one statement can't update the same row twice
+pub async fn db_record_unmatched_controllers(
+    conn: &mut PgConnection,
+    unmatched: &[UnmatchedController],
+) -> Result<(), Error> {
+    if unmatched.is_empty() {
+        return Ok(());
+    }
+
+    sqlx::query(
+        r"
+        insert into unmatched_controllers (day, callsign, frequency, num_matches, candidate_positions, num_sessions, first_seen, last_seen)
+        select (u.seen at time zone 'utc')::date, u.callsign, u.frequency, max(u.num_matches), (array_agg(u.candidate_positions order by u.seen desc))[1], count(*), min(u.seen), max(u.seen)
+        from unnest($1::text[], $2::text[], $3::int[], $4::jsonb[], $5::timestamptz[])
+            as u (callsign, frequency, num_matches, candidate_positions, seen)
+        group by 1, 2, 3
+        on conflict (day, callsign, frequency) do update set
+            num_matches = excluded.num_matches,
+            candidate_positions = excluded.candidate_positions,
+            num_sessions = unmatched_controllers.num_sessions + excluded.num_sessions,
+            last_seen = greatest(unmatched_controllers.last_seen, excluded.last_seen);",
+    )
+    .bind(unmatched.iter().map(|u| u.callsign.as_str()).collect::<Vec<_>>())
+    .bind(unmatched.iter().map(|u| u.frequency.as_str()).collect::<Vec<_>>())
+    .bind(unmatched.iter().map(|u| u.num_matches).collect::<Vec<_>>())
+    .bind(unmatched.iter().map(|u| Json(&u.candidate_positions)).collect::<Vec<_>>())
+    .bind(unmatched.iter().map(|u| u.seen).collect::<Vec<_>>())
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
+// The callsign and frequency pairs seen in the most sessions without a single match since the
+// given day
+pub async fn db_get_unmatched_controller_summaries(
+    pool: &Pool<Postgres>,
+    since: NaiveDate,
+    limit: i64,
+) -> Result<Vec<UnmatchedControllerSummary>, Error> {
+    sqlx::query_as::<_, UnmatchedControllerSummary>(
+        r"
+        select
+            callsign,
+            frequency,
+            (array_agg(num_matches order by day desc))[1] as num_matches,
+            sum(num_sessions)::bigint as num_sessions,
+            count(*) as num_days,
+            max(last_seen) as last_seen,
+            (array_agg(candidate_positions order by day desc))[1] as candidate_positions
+        from unmatched_controllers
+        where day >= $1
+        group by callsign, frequency
+        order by num_sessions desc, last_seen desc
+        limit $2;",
+    )
+    .bind(since)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn db_insert_datafeed_records(
+    conn: &mut PgConnection,
+    records: &[DatafeedRecord],
+) -> Result<PgQueryResult, Error> {
+    sqlx::query(
+        r"
+        insert into datafeed_records (update, num_tracked_controller_sessions, num_tracked_position_sessions)
+        select * from unnest($1::timestamptz[], $2::int[], $3::int[]);",
+    )
+    .bind(records.iter().map(|r| r.update).collect::<Vec<_>>())
+    .bind(records.iter().map(|r| r.num_tracked_controller_sessions).collect::<Vec<_>>())
+    .bind(records.iter().map(|r| r.num_tracked_position_sessions).collect::<Vec<_>>())
+    .execute(conn)
+    .await
+}
+
+pub async fn db_insert_network_load_record(
     pool: &Pool<Postgres>,
-    update: DateTime<Utc>,
-    num_tracked_controller_sessions: i32,
-    num_tracked_position_sessions: i32,
+    record: &NetworkLoadRecord,
 ) -> Result<PgQueryResult, Error> {
-    sqlx::query("insert into datafeed_records (update, num_tracked_controller_sessions, num_tracked_position_sessions) values ($1, $2, $3);")
-        .bind(update)
-        .bind(num_tracked_controller_sessions)
-        .bind(num_tracked_position_sessions)
+    sqlx::query("insert into network_load_records (update, connected_clients, unique_users, num_pilots, num_controllers, num_atis, num_vnas_controllers) values ($1, $2, $3, $4, $5, $6, $7);")
+        .bind(record.update)
+        .bind(record.connected_clients)
+        .bind(record.unique_users)
+        .bind(record.num_pilots)
+        .bind(record.num_controllers)
+        .bind(record.num_atis)
+        .bind(record.num_vnas_controllers)
         .execute(pool)
         .await
 }
+
+pub async fn db_upsert_datafeed_glitch(
+    conn: &mut PgConnection,
+    glitch: &DatafeedGlitch,
+) -> Result<PgQueryResult, Error> {
+    sqlx::query(
+        r"
+        insert into datafeed_glitches (id, first_update, last_update, num_ticks, baseline_controllers, min_controllers, accepted)
+        values ($1, $2, $3, $4, $5, $6, $7)
+        on conflict (id) do update set
+            last_update = excluded.last_update,
+            num_ticks = excluded.num_ticks,
+            min_controllers = excluded.min_controllers,
+            accepted = excluded.accepted;",
+    )
+    .bind(glitch.id)
+    .bind(glitch.first_update)
+    .bind(glitch.last_update)
+    .bind(glitch.num_ticks)
+    .bind(glitch.baseline_controllers)
+    .bind(glitch.min_controllers)
+    .bind(glitch.accepted)
+    .execute(conn)
+    .await
+}
+
+pub async fn db_upsert_datafeed_gap(
+    pool: &Pool<Postgres>,
+    gap: &DatafeedGap,
+) -> Result<PgQueryResult, Error> {
+    sqlx::query(
+        r"
+        insert into datafeed_gaps (id, first_skipped, last_skipped, num_skipped)
+        values ($1, $2, $3, $4)
+        on conflict (id) do update set
+            last_skipped = excluded.last_skipped,
+            num_skipped = excluded.num_skipped;",
+    )
+    .bind(gap.id)
+    .bind(gap.first_skipped)
+    .bind(gap.last_skipped)
+    .bind(gap.num_skipped)
+    .execute(pool)
+    .await
+}
@@ -0,0 +1,198 @@
+use crate::database::models::VnasPositionInfo;
+use crate::database::queries::db_get_unmatched_controller_summaries;
+use crate::matchers::single_or_no_match;
+use crate::vnas::api::{VnasApi, VnasApiError};
+use crate::vnas::extended_models::{AllPositions, Callsign, PositionExt};
+use chrono::{Days, Utc};
+use clap::Args;
+use shared::Config;
+use sqlx::postgres::PgPoolOptions;
+use std::collections::HashSet;
+use vatsim_utils::models::Controller;
+
+#[derive(Debug, Args)]
+pub struct ExplainMatchArgs {
+    /// Callsign as it appears in the datafeed, e.g. BOS_1_CTR
+    pub callsign: String,
+
+    /// Frequency as it appears in the datafeed, e.g. 134.700
+    pub frequency: String,
+}
+
+#[derive(Debug, Args)]
+pub struct UnmatchedReportArgs {
+    /// Number of days to look back, including today (UTC)
+    #[arg(long, default_value_t = 7)]
+    pub days: u64,
+
+    /// Number of callsign and frequency pairs to list
+    #[arg(long, default_value_t = 25)]
+    pub limit: i64,
+}
+
+// Lists the callsign and frequency pairs that were most often worked without matching exactly one
+// vNAS position, counting each session once for every day it was on, with the positions they could
+// have been meant for. Reads the database as the processor left it, without running migrations
+pub async fn print_unmatched_report(
+    config: &Config,
+    args: &UnmatchedReportArgs,
+) -> Result<(), sqlx::Error> {
+    let pool = PgPoolOptions::new()
+        .max_connections(1)
+        .connect(&config.postgres.connection_string)
+        .await?;
+    let since = Utc::now()
+        .date_naive()
+        .checked_sub_days(Days::new(args.days.saturating_sub(1)))
+        .unwrap_or_default();
+    let summaries = db_get_unmatched_controller_summaries(&pool, since, args.limit).await?;
+
+    println!("Unmatched vNAS controllers since {since}");
+    println!(
+        "{:<14} {:<9} {:>7} {:>8} {:>4}  {:<17}  CANDIDATES",
+        "CALLSIGN", "FREQUENCY", "MATCHES", "SESSIONS", "DAYS", "LAST SEEN"
+    );
+    for s in summaries {
+        println!(
+            "{:<14} {:<9} {:>7} {:>8} {:>4}  {:<17}  {}",
+            s.callsign,
+            s.frequency,
+            s.num_matches,
+            s.num_sessions,
+            s.num_days,
+            s.last_seen.format("%Y-%m-%d %H:%MZ"),
+            describe_candidates(&s.candidate_positions)
+        );
+    }
+
+    Ok(())
+}
+
+fn describe_candidates(candidates: &[VnasPositionInfo]) -> String {
+    if candidates.is_empty() {
+        return "-".to_string();
+    }
+
+    candidates
+        .iter()
+        .map(|p| {
+            format!(
+                "{} {:.3} ({}, {})",
+                p.callsign,
+                f64::from(p.frequency) / 1e6,
+                p.name,
+                p.parent_facility_id
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+// Fetches the current vNAS data for every ARTCC, independently of what the processor has stored
+pub async fn explain_match(args: &ExplainMatchArgs) -> Result<(), VnasApiError> {
+    let artccs = VnasApi::new()?.get_all_artccs_data().await?;
+    let positions: Vec<PositionExt> = artccs
+        .iter()
+        .flat_map(|a| a.all_positions_with_parents())
+        .collect();
+    print_match_explanation(&positions, args);
+    Ok(())
+}
+
+// Walks through matching the way the processor does it. Only positions sharing the controller's
+// simple callsign are listed, since the anchored regex rules out every other position
+pub fn print_match_explanation(positions: &[PositionExt], args: &ExplainMatchArgs) {
+    let controller = Controller {
+        callsign: args.callsign.to_owned(),
+        frequency: args.frequency.to_owned(),
+        ..Default::default()
+    };
+    let simple_callsign = controller.simple_callsign();
+    let considered: Vec<&PositionExt> = positions
+        .iter()
+        .filter(|p| p.position.simple_callsign() == simple_callsign)
+        .collect();
+    let artccs: HashSet<&str> = positions.iter().map(|p| p.artcc_id.as_str()).collect();
+
+    println!(
+        "{} on {} against {} vNAS positions in {} ARTCCs",
+        controller.callsign,
+        controller.frequency,
+        positions.len(),
+        artccs.len()
+    );
+    println!(
+        "{} positions share the simple callsign {simple_callsign}",
+        considered.len()
+    );
+
+    for p in &considered {
+        let freq_match = match p.is_freq_match(&controller.frequency) {
+            Ok(true) => "yes",
+            Ok(false) => "no",
+            Err(_) => "frequency not a number",
+        };
+        println!();
+        println!(
+            "{} {} in {} ({}), ARTCC {}",
+            p.position.id,
+            p.position.callsign,
+            p.parent_facility.id,
+            p.parent_facility.name,
+            p.artcc_id
+        );
+        println!(
+            "    frequency {:.3}, starred {}",
+            p.position.frequency as f64 / 1e6,
+            if p.position.starred { "yes" } else { "no" }
+        );
+        println!("    regex {}", p.regex.as_str());
+        println!(
+            "    callsign matched: {}, frequency matched: {freq_match}",
+            if p.regex.is_match(&controller.callsign) {
+                "yes"
+            } else {
+                "no"
+            }
+        );
+    }
+
+    let matched: Vec<&PositionExt> = considered
+        .iter()
+        .copied()
+        .filter(|p| p.is_match(&controller))
+        .collect();
+    let starred = matched.iter().filter(|p| p.position.starred).count();
+    println!();
+    // Sessions are associated with every match, see `MatcherIndex::all_matches`
+    println!("Matched: {}", describe_positions(&matched));
+    let single = single_or_no_match(positions, &controller);
+    let reason = match (matched.len(), starred, single) {
+        (0, _, _) => "no position matched".to_owned(),
+        (1, _, _) => "the only match".to_owned(),
+        (n, 1, Some(_)) => format!("the only starred position of {n} matches"),
+        (n, 0, _) => format!("{n} matches, none of them starred"),
+        (n, s, _) => format!("{n} matches, {s} of them starred"),
+    };
+    println!(
+        "single_or_no_match: {} ({reason})",
+        single.map_or("none".to_owned(), |p| describe_positions(&[p]))
+    );
+}
+
+fn describe_positions(positions: &[&PositionExt]) -> String {
+    if positions.is_empty() {
+        return "none".to_string();
+    }
+
+    positions
+        .iter()
+        .map(|p| {
+            format!(
+                "{} ({}, {})",
+                p.position.id, p.position.callsign, p.parent_facility.id
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
@@ -0,0 +1,220 @@
+use crate::database::models::DatafeedGlitch;
+use crate::telemetry::DATAFEED_GLITCHES;
+use chrono::{DateTime, Utc};
+use metrics::counter;
+use shared::GlitchGuardConfig;
+use std::collections::VecDeque;
+use tracing::{info, warn};
+
+// Watches the number of vNAS controllers per tick for sudden drops, which are more likely a
+// truncated datafeed than everyone logging off at once. Sessions missing from such a tick are held
+// for a few ticks, and only ended if the drop is still there afterwards
+#[derive(Clone)]
+pub struct GlitchGuard {
+    max_drop_share: f64,
+    min_baseline: f64,
+    recent_ticks: usize,
+    hold_ticks: u32,
+    recent: VecDeque<usize>,
+    current: Option<DatafeedGlitch>,
+}
+
+pub struct GlitchCheck {
+    // Sessions missing from this tick should be left as they are
+    pub hold_sessions: bool,
+    // The glitch this tick is part of, to be written with the tick
+    pub glitch: Option<DatafeedGlitch>,
+}
+
+impl GlitchGuard {
+    pub fn new(config: &GlitchGuardConfig) -> GlitchGuard {
+        GlitchGuard {
+            max_drop_share: config.max_drop_share,
+            min_baseline: config.min_baseline_controllers as f64,
+            recent_ticks: config.recent_ticks.max(1),
+            hold_ticks: config.hold_ticks,
+            recent: VecDeque::new(),
+            current: None,
+        }
+    }
+
+    pub fn check(&mut self, num_controllers: usize, update: DateTime<Utc>) -> GlitchCheck {
+        // Average over recent ticks that weren't themselves suspected glitches
+        let baseline = (!self.recent.is_empty())
+            .then(|| self.recent.iter().sum::<usize>() as f64 / self.recent.len() as f64);
+        let suspected = baseline.is_some_and(|baseline| {
+            baseline >= self.min_baseline
+                && (num_controllers as f64) < baseline * (1.0 - self.max_drop_share)
+        });
+
+        if !suspected {
+            if let Some(glitch) = self.current.take() {
+                info!(
+                    first_update = %glitch.first_update,
+                    num_ticks = glitch.num_ticks,
+                    num_controllers,
+                    "Datafeed recovered from suspected glitch"
+                );
+            }
+            self.push_recent(num_controllers);
+            return GlitchCheck {
+                hold_sessions: false,
+                glitch: None,
+            };
+        }
+
+        let glitch = self.current.get_or_insert_with(|| {
+            counter!(DATAFEED_GLITCHES).increment(1);
+            DatafeedGlitch::starting_at(update, baseline.unwrap_or_default())
+        });
+        glitch.extend_to(update, num_controllers);
+
+        if glitch.num_ticks as u32 <= self.hold_ticks {
+            warn!(
+                num_controllers,
+                baseline_controllers = glitch.baseline_controllers,
+                num_ticks = glitch.num_ticks,
+                "Suspected datafeed glitch, holding sessions"
+            );
+            return GlitchCheck {
+                hold_sessions: true,
+                glitch: Some(glitch.clone()),
+            };
+        }
+
+        // Still down after holding, so the drop is taken as real and becomes the new baseline
+        glitch.accepted = true;
+        let glitch = self.current.take();
+        warn!(
+            num_controllers,
+            "Controller count stayed down after suspected glitch, ending held sessions"
+        );
+        self.recent.clear();
+        self.push_recent(num_controllers);
+        GlitchCheck {
+            hold_sessions: false,
+            glitch,
+        }
+    }
+
+    fn push_recent(&mut self, num_controllers: usize) {
+        if self.recent.len() == self.recent_ticks {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(num_controllers);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn guard() -> GlitchGuard {
+        GlitchGuard::new(&GlitchGuardConfig {
+            max_drop_share: 0.5,
+            min_baseline_controllers: 10,
+            recent_ticks: 3,
+            hold_ticks: 2,
+        })
+    }
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    // Feeds one tick per count, 15 seconds apart, returning whether each was held
+    fn run(guard: &mut GlitchGuard, counts: &[usize]) -> Vec<bool> {
+        let start = at("2024-07-21T12:00:00Z");
+        counts
+            .iter()
+            .enumerate()
+            .map(|(i, n)| {
+                let update = start + Duration::seconds(15 * i as i64);
+                guard.check(*n, update).hold_sessions
+            })
+            .collect()
+    }
+
+    #[test]
+    fn baseline_is_average_of_recent_ticks() {
+        let mut guard = guard();
+        // Nothing to compare the first tick against
+        assert_eq!(run(&mut guard, &[0]), [false]);
+
+        // Only the last three ticks count, so 19 is a drop from 40 rather than from 32.5
+        let mut guard = self::guard();
+        assert_eq!(
+            run(&mut guard, &[10, 40, 40, 40, 19]),
+            [false, false, false, false, true]
+        );
+
+        // Gradual declines and quiet hours are taken at face value
+        let mut guard = self::guard();
+        assert_eq!(run(&mut guard, &[40, 32, 26, 21, 17]), [false; 5]);
+        let mut guard = self::guard();
+        assert_eq!(run(&mut guard, &[9, 9, 9, 1]), [false; 4]);
+    }
+
+    #[test]
+    fn sudden_drop_holds_sessions() {
+        let mut guard = guard();
+        run(&mut guard, &[40, 40, 40]);
+
+        let start = at("2024-07-21T13:00:00Z");
+        let first = guard.check(10, start);
+        assert!(first.hold_sessions);
+        let glitch = first.glitch.unwrap();
+        assert_eq!(glitch.first_update, start);
+        assert_eq!(glitch.num_ticks, 1);
+        assert_eq!(glitch.baseline_controllers, 40.0);
+        assert_eq!(glitch.min_controllers, 10);
+        assert!(!glitch.accepted);
+
+        let second = guard.check(15, start + Duration::seconds(15));
+        assert!(second.hold_sessions);
+        let extended = second.glitch.unwrap();
+        assert_eq!(extended.id, glitch.id);
+        assert_eq!(extended.last_update, start + Duration::seconds(15));
+        assert_eq!(extended.num_ticks, 2);
+        assert_eq!(extended.min_controllers, 10);
+    }
+
+    #[test]
+    fn drop_lasting_past_hold_is_accepted() {
+        let mut guard = guard();
+        assert_eq!(
+            run(&mut guard, &[40, 40, 40, 10, 10]),
+            [false, false, false, true, true]
+        );
+
+        let start = at("2024-07-21T13:00:00Z");
+        let expired = guard.check(10, start);
+        assert!(!expired.hold_sessions);
+        let glitch = expired.glitch.unwrap();
+        assert!(glitch.accepted);
+        assert_eq!(glitch.num_ticks, 3);
+
+        // The lower count is the new baseline
+        let next = guard.check(10, start + Duration::seconds(15));
+        assert!(!next.hold_sessions);
+        assert!(next.glitch.is_none());
+    }
+
+    #[test]
+    fn recovery_ends_glitch_without_skewing_baseline() {
+        let mut guard = guard();
+        assert_eq!(
+            run(&mut guard, &[40, 40, 40, 10]),
+            [false, false, false, true]
+        );
+
+        let start = at("2024-07-21T13:00:00Z");
+        let recovered = guard.check(39, start);
+        assert!(!recovered.hold_sessions);
+        assert!(recovered.glitch.is_none());
+
+        // The held tick was left out of the baseline, which would otherwise be below 38
+        assert!(guard.check(19, start + Duration::seconds(15)).hold_sessions);
+    }
+}
@@ -0,0 +1,1150 @@
+use crate::database::models::{
+    Artcc, ControllerSession, ControllerSessionSegment, DatafeedGap, DatafeedRecord,
+    NetworkLoadRecord, PositionSession, UnmatchedController, VnasFacilityInfo, VnasPositionInfo,
+};
+use crate::database::queries::{
+    db_get_active_controller_sessions, db_get_active_position_sessions, db_get_all_artccs,
+    db_get_cooldown_controller_sessions, db_get_cooldown_position_sessions, db_get_last_departures,
+    db_get_latest_fetch_record, db_get_open_controller_session_segments,
+    db_insert_datafeed_records, db_insert_network_load_record, db_insert_position_handovers,
+    db_insert_vnas_fetch_record, db_record_unmatched_controllers, db_repoint_controller_sessions,
+    db_save_controller_session_segments, db_save_controller_sessions, db_save_position_sessions,
+    db_update_vnas_artcc, db_update_vnas_facility, db_update_vnas_position, db_upsert_datafeed_gap,
+    db_upsert_datafeed_glitch,
+};
+use crate::glitch_guard::{GlitchCheck, GlitchGuard};
+use crate::matchers::MatcherIndex;
+use crate::messages::decode_datafeed_message;
+use crate::session_trackers::ActiveSessionTrackerSource::{FromDatabase, NewlyCreated};
+use crate::session_trackers::{
+    ActiveSessionsMap, ControllerSessionTracker, PositionSessionTracker,
+};
+use crate::telemetry::{
+    describe_metrics, record_session_event, SessionEvent, SessionKind, DATAFEED_GAPS,
+    DECODE_FAILURES, DROPPED_MESSAGES, POSITION_HANDOVERS, PROCESSING_DURATION, QUEUE_LAG,
+    READY_DATABASE, READY_MATCHERS, READY_TRANSPORT, RECEIVE_FAILURES, STALE_MESSAGES,
+    UNMATCHED_CONTROLLERS, VNAS_REFRESHES,
+};
+use crate::vnas::api::{VnasApi, VnasApiError};
+use crate::vnas::api_dtos::ArtccRoot;
+use crate::vnas::extended_models::{AllPositions, Callsign, PositionExt};
+use chrono::{DateTime, Utc};
+use futures::future::join_all;
+use metrics::{counter, gauge, histogram};
+use shared::retry::{retry_with_backoff, Transient};
+use shared::shutdown::Shutdown;
+use shared::telemetry::Readiness;
+use shared::transport::DatafeedTransport;
+use shared::{Config, PositionIdentity, SessionsConfig, StalePolicy};
+use sqlx::migrate::MigrateError;
+use sqlx::postgres::types::PgInterval;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::{Pool, Postgres};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, instrument, trace, warn};
+use uuid::Uuid;
+use vatsim_utils::models::Controller;
+
+mod database;
+pub mod diagnostics;
+mod glitch_guard;
+pub mod matchers;
+mod messages;
+mod session_trackers;
+pub mod telemetry;
+pub mod vnas;
+
+// Upper bound on how long a due vNAS refresh, or a retry of a failed one, can wait to be noticed
+const VNAS_REFRESH_CHECK_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error("error with database")]
+    Database(#[from] sqlx::Error),
+
+    #[error("error updated vNAS data")]
+    VnasDataUpdate(#[from] VnasDataUpdateError),
+
+    #[error("could not apply migrations")]
+    Migration(#[from] MigrateError),
+
+    #[error("vNAS data was not updated")]
+    VnasDataNotUpdated,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum VnasDataUpdateError {
+    #[error("error with database")]
+    DbError(#[from] sqlx::Error),
+
+    #[error("could not fetch data")]
+    ApiError(#[from] VnasApiError),
+}
+
+// Database errors are only retried when the server couldn't be reached or isn't accepting
+// connections yet. A failed vNAS fetch is always retried, as the API may just be down
+impl Transient for InitError {
+    fn is_transient(&self) -> bool {
+        match self {
+            InitError::Database(e)
+            | InitError::VnasDataUpdate(VnasDataUpdateError::DbError(e))
+            | InitError::Migration(MigrateError::Execute(e)) => is_transient_db_error(e),
+            InitError::VnasDataUpdate(VnasDataUpdateError::ApiError(_))
+            | InitError::VnasDataNotUpdated => true,
+            InitError::Migration(_) => false,
+        }
+    }
+}
+
+fn is_transient_db_error(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut => true,
+        // Connection exceptions (class 08), and the server starting up or shutting down
+        sqlx::Error::Database(e) => e
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code.starts_with("57P")),
+        _ => false,
+    }
+}
+
+pub async fn run_processor(
+    config: &Config,
+    mut transport: Box<dyn DatafeedTransport>,
+    readiness: Readiness,
+    mut shutdown: Shutdown,
+) -> Result<(), InitError> {
+    // Overall flow
+    // - Initialize DB if needed, and do initial fetch if no vNAS data fetches have been done
+    // - Initialize datafeed queue connection
+    // - Start datafeed message receive loop
+    // - With message:
+    // - Check in the background whether the last vNAS data fetch is older than the refresh interval. If yes, fetch new data, update any ARTCCS that need updating and swap in the new matchers
+    // -    Process existing active sessions (keep open or close) and add new sessions if needed, even if no USA controllers are online
+    // -    Aggregate stats
+
+    describe_metrics();
+    readiness.register(READY_DATABASE);
+    readiness.register(READY_MATCHERS);
+    // The transport is connected before it is handed to us
+    readiness.set(READY_TRANSPORT, true);
+
+    let Some(db_pool) = retry_with_backoff("initialize database", &mut shutdown, || {
+        initialize_db(&config.postgres.connection_string)
+    })
+    .await?
+    else {
+        return Ok(());
+    };
+    readiness.set(READY_DATABASE, true);
+
+    let vnas_max_age = chrono::Duration::minutes(config.vnas.refresh_interval_minutes.into());
+    let Some(vnas_positions) =
+        retry_with_backoff("initialize position matchers", &mut shutdown, || async {
+            match refresh_vnas_positions(&db_pool, true, vnas_max_age).await {
+                Ok(Some(vnas_positions)) => Ok(vnas_positions),
+                Ok(None) => Err(InitError::VnasDataNotUpdated),
+                Err(e) => Err(InitError::from(e)),
+            }
+        })
+        .await?
+    else {
+        return Ok(());
+    };
+    readiness.set(READY_MATCHERS, true);
+
+    // Replaced wholesale by the refresh task, and read once per tick, so a tick always matches
+    // against a single consistent set of positions
+    let (matchers_sender, matchers) = watch::channel(Arc::new(vnas_positions));
+    tokio::spawn(refresh_vnas_positions_periodically(
+        db_pool.clone(),
+        vnas_max_age,
+        matchers_sender,
+        shutdown.clone(),
+    ));
+
+    let stale_after = config
+        .queue
+        .stale_after_minutes
+        .map(|minutes| chrono::Duration::minutes(minutes.into()));
+    let mut current_gap: Option<DatafeedGap> = None;
+    let mut num_caught_up = 0;
+
+    let mut state = ProcessorState {
+        active_sessions: None,
+        glitch_guard: GlitchGuard::new(&config.sessions.glitch_guard),
+        last_saved: None,
+    };
+    // Messages are acknowledged once their tick has been saved
+    let mut unsaved_messages: Vec<String> = vec![];
+
+    // Runs until shutdown is requested. A message that is being processed when that happens is
+    // finished and acked first, so it isn't processed a second time after a restart
+    while !shutdown.is_requested() {
+        let msg = transport.receive().await;
+
+        if let Err(e) = &msg {
+            warn!(error = ?e, "Error receiving message from datafeed queue");
+            counter!(RECEIVE_FAILURES).increment(1);
+            readiness.set(READY_TRANSPORT, false);
+            continue;
+        }
+        readiness.set(READY_TRANSPORT, true);
+
+        if let Some(message) = msg.expect("Error receiving message from datafeed queue") {
+            // Messages that fail to decode are left unacknowledged, so a message from a newer
+            // fetcher stays queued until a processor that understands it picks it up. One that
+            // keeps failing is dropped eventually, so it can't hold up the queue for good
+            let msg_struct = match decode_datafeed_message(&message.payload) {
+                Ok((header, msg)) => {
+                    trace!(?header, "Decoded datafeed message");
+                    msg
+                }
+                Err(e) => {
+                    warn!(
+                        error = ?e,
+                        message_id = message.id,
+                        delivery_count = message.delivery_count,
+                        "Error decoding message from datafeed queue"
+                    );
+                    counter!(DECODE_FAILURES).increment(1);
+                    if message.delivery_count >= config.queue.max_decode_attempts {
+                        warn!(
+                            message_id = message.id,
+                            "Dropping datafeed message that could not be decoded"
+                        );
+                        counter!(DROPPED_MESSAGES).increment(1);
+                        if let Err(e) = transport.ack(&message.id).await {
+                            warn!(error = ?e, "Error acknowledging message in datafeed queue");
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            let queue_lag = Utc::now() - msg_struct.update;
+            gauge!(QUEUE_LAG).set(queue_lag.num_milliseconds() as f64 / 1000.0);
+
+            let is_stale = stale_after.is_some_and(|stale_after| queue_lag > stale_after);
+            if is_stale && config.queue.stale_policy == StalePolicy::Skip {
+                counter!(STALE_MESSAGES, "policy" => "skip").increment(1);
+
+                // Consecutive skipped messages are recorded as a single gap
+                let gap = current_gap.get_or_insert_with(|| {
+                    counter!(DATAFEED_GAPS).increment(1);
+                    DatafeedGap::starting_at(msg_struct.update)
+                });
+                gap.extend_to(msg_struct.update);
+                if let Err(e) = db_upsert_datafeed_gap(&db_pool, gap).await {
+                    warn!(error = ?e, "Error saving datafeed gap");
+                }
+
+                if let Err(e) = transport.ack(&message.id).await {
+                    warn!(error = ?e, "Error acknowledging message in datafeed queue");
+                }
+                continue;
+            }
+
+            if is_stale {
+                counter!(STALE_MESSAGES, "policy" => "catch_up").increment(1);
+                num_caught_up += 1;
+            } else {
+                if let Some(gap) = current_gap.take() {
+                    warn!(
+                        first_skipped = %gap.first_skipped,
+                        last_skipped = %gap.last_skipped,
+                        num_skipped = gap.num_skipped,
+                        "Skipped stale datafeed messages"
+                    );
+                }
+                if num_caught_up > 0 {
+                    info!(num_caught_up, "Caught up on stale datafeed messages");
+                    num_caught_up = 0;
+                }
+            }
+
+            let vnas_controllers: Vec<&Controller> = msg_struct
+                .controllers
+                .iter()
+                .filter(|c| is_active_vnas_controller(c))
+                .collect();
+
+            // Messages from fetchers older than schema version 3 carry no general block
+            if let Some(general) = &msg_struct.general {
+                let record = NetworkLoadRecord {
+                    update: msg_struct.update,
+                    connected_clients: general.connected_clients as i32,
+                    unique_users: general.unique_users as i32,
+                    num_pilots: msg_struct.pilots.len() as i32,
+                    num_controllers: msg_struct.controllers.len() as i32,
+                    num_atis: msg_struct.atis.len() as i32,
+                    num_vnas_controllers: vnas_controllers.len() as i32,
+                };
+                if let Err(e) = db_insert_network_load_record(&db_pool, &record).await {
+                    warn!(error = ?e, "Error saving network load record");
+                }
+            }
+
+            // Ticks without any vNAS controllers are processed too, so sessions still open are
+            // ended and cooled down on time during quiet hours. Stale ticks are only processed in
+            // memory, and saved together with the first tick that isn't, so catching up on a
+            // backlog writes sessions once rather than for every message
+            let save = !is_stale || unsaved_messages.len() + 1 >= config.queue.catch_up_batch_size;
+            let vnas_positions = matchers.borrow().clone();
+            let start = Instant::now();
+            let result = process_datafeed(
+                vnas_controllers,
+                msg_struct.update,
+                &vnas_positions,
+                &db_pool,
+                &mut state,
+                &config.sessions,
+                save,
+            )
+            .await;
+            histogram!(PROCESSING_DURATION).record(start.elapsed().as_secs_f64());
+
+            unsaved_messages.push(message.id);
+            if let Err(e) = &result {
+                warn!(error = ?e, "Error processing datafeed")
+            }
+            if save || result.is_err() {
+                readiness.set(READY_DATABASE, result.is_ok());
+            }
+            if save {
+                ack_messages(transport.as_mut(), &mut unsaved_messages).await;
+            }
+        } else {
+            // The queue ran dry before a tick came along that isn't stale
+            if !unsaved_messages.is_empty() {
+                save_and_ack(
+                    &db_pool,
+                    &mut state,
+                    &config.sessions,
+                    &readiness,
+                    transport.as_mut(),
+                    &mut unsaved_messages,
+                )
+                .await;
+            }
+            trace!("No message received from queue, sleeping");
+            shutdown.sleep(Duration::from_secs(1)).await
+        }
+    }
+
+    // Ticks still unsaved from catching up are saved too, rather than processed again after a
+    // restart
+    if !unsaved_messages.is_empty() {
+        save_and_ack(
+            &db_pool,
+            &mut state,
+            &config.sessions,
+            &readiness,
+            transport.as_mut(),
+            &mut unsaved_messages,
+        )
+        .await;
+    }
+
+    info!("Processor stopped");
+    Ok(())
+}
+
+// Sessions and glitch guard carried from one tick to the next
+struct ProcessorState {
+    // Loaded from the database on first use
+    active_sessions: Option<ActiveSessionsMap>,
+    glitch_guard: GlitchGuard,
+    // Both as they were after the last save, while ticks processed since are still to be saved. If
+    // saving fails, this is what the database still holds and processing carries on from there.
+    // Reloading instead would lose the time sessions moved on since the last full flush
+    last_saved: Option<(ActiveSessionsMap, GlitchGuard)>,
+}
+
+async fn ack_messages(transport: &mut dyn DatafeedTransport, message_ids: &mut Vec<String>) {
+    for id in message_ids.drain(..) {
+        if let Err(e) = transport.ack(&id).await {
+            warn!(error = ?e, "Error acknowledging message in datafeed queue");
+        }
+    }
+}
+
+// Saves the ticks processed since the last save and acknowledges their messages. They are
+// acknowledged even if saving fails, as those ticks are dropped then, like any tick that fails
+async fn save_and_ack(
+    pool: &Pool<Postgres>,
+    state: &mut ProcessorState,
+    sessions_config: &SessionsConfig,
+    readiness: &Readiness,
+    transport: &mut dyn DatafeedTransport,
+    message_ids: &mut Vec<String>,
+) {
+    let result = save_processed_ticks(pool, state, sessions_config).await;
+    readiness.set(READY_DATABASE, result.is_ok());
+    if let Err(e) = result {
+        warn!(error = ?e, "Error saving datafeed")
+    }
+    ack_messages(transport, message_ids).await;
+}
+
+async fn initialize_db(connection_string: &str) -> Result<Pool<Postgres>, InitError> {
+    // Create Db connection pool
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(connection_string)
+        .await?;
+
+    // Run any new migrations
+    sqlx::migrate!("./migrations").run(&pool).await?;
+
+    Ok(pool)
+}
+
+fn should_update_artcc(new_fetched_artcc: &ArtccRoot, existing_db_artccs: &[Artcc]) -> bool {
+    let mut filtered = existing_db_artccs
+        .iter()
+        .filter(|a| a.id == new_fetched_artcc.id);
+
+    match filtered.next() {
+        Some(existing_artcc) => new_fetched_artcc.last_updated_at > existing_artcc.last_updated,
+        None => true,
+    }
+}
+
+async fn update_artcc_in_db(pool: &Pool<Postgres>, artcc: &ArtccRoot) -> Result<(), sqlx::Error> {
+    // Insert or update Artcc root
+    if let Err(e) = db_update_vnas_artcc(pool, artcc).await {
+        warn!(error = ?e, "Error updating ARTCCs in database");
+        return Err(e);
+    }
+
+    // Insert or update all Facilities in Artcc
+    for f in artcc.all_facilities_with_info() {
+        if let Err(e) = db_update_vnas_facility(pool, &f).await {
+            warn!(error = ?e, facility_name = f.facility.name, "Error updating Facility in database");
+            return Err(e);
+        }
+    }
+
+    // Insert or update all Positions in Artcc
+    for p in artcc.all_positions_with_parents() {
+        if let Err(e) = db_update_vnas_position(pool, &p, artcc).await {
+            warn!(error = ?e, position_name = p.position.name, "Error updating Position in database");
+            return Err(e);
+        }
+    }
+
+    Ok(())
+}
+
+// Checks for due vNAS refreshes until shutdown, publishing each refreshed set of matchers. Checks
+// happen more often than refreshes are due, so a failed fetch is retried without waiting out a
+// full interval
+async fn refresh_vnas_positions_periodically(
+    pool: Pool<Postgres>,
+    max_age: chrono::Duration,
+    matchers: watch::Sender<Arc<MatcherIndex>>,
+    mut shutdown: Shutdown,
+) {
+    let check_interval = max_age
+        .to_std()
+        .unwrap_or_default()
+        .clamp(Duration::from_secs(60), VNAS_REFRESH_CHECK_INTERVAL);
+
+    loop {
+        shutdown.sleep(check_interval).await;
+        if shutdown.is_requested() {
+            break;
+        }
+
+        match refresh_vnas_positions(&pool, false, max_age).await {
+            Ok(Some(index)) => {
+                info!(
+                    num_positions = index.positions().len(),
+                    "Refreshed vNAS position matchers"
+                );
+                matchers.send_replace(Arc::new(index));
+            }
+            Ok(None) => trace!("vNAS data not due for refresh"),
+            Err(e) => warn!(error = ?e, "Error refreshing vNAS data"),
+        }
+    }
+}
+
+// Runs a vNAS data refresh, records its outcome and indexes any refreshed positions for matching
+async fn refresh_vnas_positions(
+    pool: &Pool<Postgres>,
+    force_update: bool,
+    max_age: chrono::Duration,
+) -> Result<Option<MatcherIndex>, VnasDataUpdateError> {
+    let result = update_all_artccs_in_db(pool, force_update, max_age).await;
+    let outcome = match &result {
+        Ok(Some(_)) => "updated",
+        Ok(None) => "not_due",
+        Err(VnasDataUpdateError::ApiError(_)) => "api_error",
+        Err(VnasDataUpdateError::DbError(_)) => "db_error",
+    };
+    counter!(VNAS_REFRESHES, "outcome" => outcome).increment(1);
+    result.map(|positions| positions.map(MatcherIndex::new))
+}
+
+#[instrument(skip(pool))]
+async fn update_all_artccs_in_db(
+    pool: &Pool<Postgres>,
+    force_update: bool,
+    max_age: chrono::Duration,
+) -> Result<Option<Vec<PositionExt>>, VnasDataUpdateError> {
+    // Get record of latest vNAS data fetch. Update if none or stale data (older than max_age)
+    let latest_record = db_get_latest_fetch_record(pool).await?;
+
+    // Update if we've never initialized DB or haven't done it within max_age, or we want to force update
+    if latest_record.is_none()
+        || (Utc::now() - latest_record.expect("No vNAS Fetch Record").update_time) > max_age
+        || force_update
+    {
+        let fetched_artccs = VnasApi::new().unwrap().get_all_artccs_data().await?;
+        let db_artccs = db_get_all_artccs(pool).await?;
+
+        let needs_update = fetched_artccs
+            .iter()
+            .filter(|a| should_update_artcc(a, &db_artccs));
+
+        // Apply update to all Artccs that need update and await joined result
+        let results = join_all(needs_update.map(|artcc| update_artcc_in_db(pool, artcc))).await;
+
+        // Store record of vNAS data check. If any errors, log as unsuccessful
+        db_insert_vnas_fetch_record(pool, !results.iter().any(|r| r.is_err())).await?;
+
+        let position_matchers: Vec<PositionExt> = fetched_artccs
+            .iter()
+            .flat_map(|f| f.all_positions_with_parents())
+            .collect();
+
+        return Ok(Some(position_matchers));
+    }
+
+    Ok(None)
+}
+
+async fn process_datafeed(
+    datafeed_controllers: Vec<&Controller>,
+    datafeed_timestamp: DateTime<Utc>,
+    vnas_positions: &MatcherIndex,
+    pool: &Pool<Postgres>,
+    state: &mut ProcessorState,
+    sessions_config: &SessionsConfig,
+    save: bool,
+) -> Result<(), sqlx::Error> {
+    // Get all existing active controllers in DB as vector. Convert to Hashmap
+    // Get all existing position sessions in DB as vector. Convert to Hashmap
+    // Get all active controllers from datafeed as vector
+    // For each controller in datafeed
+    //      - If controller already exists in Hashmap (i.e., already marked active), update last_updated time for controller session and associated positon session
+    //              - Mark controller as still active in existing controllers Hashmap
+    //              - Mark position session as still active in existing position sessions Hashmap
+    //      - If controller does not exist
+    //              - Check to see if position session exists. If no, create new and tag as still active. If yes, tag as still active
+    //              - Create new controller session (with PostionMatcher) and tag as still active and associate with position session and add to Hashmap
+    // For each position session in Hashmap
+    //      - If not tagged active, mark ended
+    // For each controller session in Hashmap
+    //      - If not tagged active, mark ended
+    // Write all positions and controller sessions to DB (including active / not active state)
+
+    let mut active = match state.active_sessions.take() {
+        Some(active) => active,
+        None => load_active_sessions(pool).await?,
+    };
+    // The first tick after a save keeps how things were, to go back to if saving fails
+    let last_saved = state
+        .last_saved
+        .take()
+        .unwrap_or_else(|| (active.clone(), state.glitch_guard.clone()));
+
+    // A sharp drop in controllers is more likely a truncated datafeed than a mass logoff, so
+    // sessions missing from the tick may be held rather than ended
+    let glitch_check = state
+        .glitch_guard
+        .check(datafeed_controllers.len(), datafeed_timestamp);
+
+    // Keys of every connection in this tick, so a session whose connection is still listed is never
+    // mistaken for one its controller reconnected from
+    let datafeed_keys: HashSet<String> = datafeed_controllers
+        .iter()
+        .filter_map(|c| try_make_controller_key(c))
+        .collect();
+
+    for datafeed_controller in datafeed_controllers {
+        let Some(controller_key) = try_make_controller_key(datafeed_controller) else {
+            warn!(
+                cid = datafeed_controller.cid,
+                login_time = datafeed_controller.logon_time,
+                callsign = datafeed_controller.callsign,
+                "Error parsing login time"
+            );
+            continue;
+        };
+        let matches = vnas_positions.all_matches(datafeed_controller);
+        let matches = matches.as_deref();
+        let position_key = make_position_key(
+            datafeed_controller,
+            matches,
+            sessions_config.position_identity,
+        );
+
+        // If we have already tracked the controller, mark controller and position as active
+        if active.controller_exists(&controller_key) {
+            active.mark_controller_active_from(
+                &controller_key,
+                datafeed_controller,
+                datafeed_timestamp,
+            );
+            follow_position(
+                &mut active,
+                &controller_key,
+                &position_key,
+                datafeed_controller,
+                datafeed_timestamp,
+                matches,
+            );
+        } else if active.cooldown_controller_exists(&controller_key) {
+            active.resurrect_controller_from(
+                &controller_key,
+                datafeed_controller,
+                datafeed_timestamp,
+            );
+            follow_position(
+                &mut active,
+                &controller_key,
+                &position_key,
+                datafeed_controller,
+                datafeed_timestamp,
+                matches,
+            );
+
+        // The controller logged on again, e.g. after a client crash, before their previous session
+        // for this position completed. Carry on with that session rather than opening a new one
+        } else if let Some(reconnected_key) =
+            active.find_reconnected_controller(datafeed_controller, &datafeed_keys)
+        {
+            active.reconnect_controller_from(
+                &reconnected_key,
+                datafeed_controller,
+                datafeed_timestamp,
+            );
+            follow_position(
+                &mut active,
+                &controller_key,
+                &position_key,
+                datafeed_controller,
+                datafeed_timestamp,
+                matches,
+            );
+
+        // We are currently tracking this position, so create new controller tracker and attach to position
+        // Don't check for positions in cooldown state as we don't want to resurrect them with a new controller
+        } else if active.position_exists(&position_key) {
+            let position_tracker = active.get_position(&position_key).unwrap();
+
+            // There is an existing position, so create new controller session attached to it
+            if let Some(new_controller_session_tracker) = create_new_controller_session_tracker(
+                datafeed_controller,
+                datafeed_timestamp,
+                matches,
+                &position_tracker.position_session,
+            ) {
+                active.insert_new_controller(new_controller_session_tracker);
+                record_session_event(SessionKind::Controller, SessionEvent::Opened);
+                active.mark_position_active_from(
+                    &position_key,
+                    datafeed_controller,
+                    datafeed_timestamp,
+                );
+            }
+        // We aren't currently tracking this position or controller, so create both
+        } else if let Some(new_position_session_tracker) = create_new_position_session_tracker(
+            datafeed_controller,
+            datafeed_timestamp,
+            matches,
+            &position_key,
+        ) {
+            if let Some(new_controller_session_tracker) = create_new_controller_session_tracker(
+                datafeed_controller,
+                datafeed_timestamp,
+                matches,
+                &new_position_session_tracker.position_session,
+            ) {
+                active.insert_new_position(new_position_session_tracker);
+                active.insert_new_controller(new_controller_session_tracker);
+                record_session_event(SessionKind::Position, SessionEvent::Opened);
+                record_session_event(SessionKind::Controller, SessionEvent::Opened);
+            }
+        } else {
+            warn!(
+                connected_callsign = datafeed_controller.callsign,
+                cid = datafeed_controller.cid,
+                "Could not find or create position session tracker"
+            );
+        }
+
+        let Some(c) = active.controllers.get_mut(&controller_key) else {
+            continue;
+        };
+        // A controller who moved to another frequency may be working other positions now
+        if c.frequency_changed {
+            let matched = matches
+                .map(|m| m.iter().copied().map(VnasPositionInfo::from).collect())
+                .unwrap_or_default();
+            c.add_vnas_positions(matched);
+        }
+        // Unmatched controllers are recorded once a day for as long as they stay on, whether
+        // their session is new, carried on or continued past midnight, and again for every
+        // frequency they move to
+        let today = datafeed_timestamp.date_naive();
+        if c.frequency_changed || c.unmatched_checked_on != Some(today) {
+            c.unmatched_checked_on = Some(today);
+            active
+                .unmatched_controllers
+                .extend(find_unmatched_controller(
+                    datafeed_controller,
+                    datafeed_timestamp,
+                    matches,
+                    vnas_positions,
+                ));
+        }
+        c.frequency_changed = false;
+    }
+
+    finish_tick(
+        &mut active,
+        datafeed_timestamp,
+        vnas_positions,
+        sessions_config,
+        &glitch_check,
+    );
+    state.active_sessions = Some(active);
+    state.last_saved = Some(last_saved);
+
+    if save {
+        save_processed_ticks(pool, state, sessions_config).await?;
+    }
+    Ok(())
+}
+
+// Saves everything from the ticks processed since the last save. If that fails, the sessions and
+// glitch guard go back to how they were after the last save and those ticks are dropped
+async fn save_processed_ticks(
+    pool: &Pool<Postgres>,
+    state: &mut ProcessorState,
+    sessions_config: &SessionsConfig,
+) -> Result<(), sqlx::Error> {
+    // Nothing was processed since the last save
+    let Some(last_saved) = state.last_saved.take() else {
+        return Ok(());
+    };
+    let Some(mut active) = state.active_sessions.take() else {
+        return Ok(());
+    };
+
+    if let Err(e) = save_all_sessions(pool, &mut active, sessions_config).await {
+        (state.active_sessions, state.glitch_guard) = (Some(last_saved.0), last_saved.1);
+        return Err(e);
+    }
+    state.active_sessions = Some(active);
+    Ok(())
+}
+
+async fn load_active_sessions(pool: &Pool<Postgres>) -> Result<ActiveSessionsMap, sqlx::Error> {
+    let mut open_segments: HashMap<_, _> = db_get_open_controller_session_segments(pool)
+        .await?
+        .into_iter()
+        .map(|s| (s.controller_session_id, s))
+        .collect();
+    let mut load_controller = |c: ControllerSession| {
+        let mut tracker = ControllerSessionTracker::new(c, FromDatabase);
+        tracker.segment = open_segments.remove(&tracker.controller_session.id);
+        (
+            make_controller_key(
+                &tracker.controller_session.cid.to_string(),
+                tracker.controller_session.last_logon_time,
+            ),
+            tracker,
+        )
+    };
+
+    let controllers: HashMap<_, _> = db_get_active_controller_sessions(pool)
+        .await?
+        .into_iter()
+        .map(&mut load_controller)
+        .collect();
+
+    let cooldown_controllers: HashMap<_, _> = db_get_cooldown_controller_sessions(pool)
+        .await?
+        .into_iter()
+        .map(&mut load_controller)
+        .collect();
+
+    let cooldown_positions: HashMap<_, _> = db_get_cooldown_position_sessions(pool)
+        .await?
+        .into_iter()
+        .map(|p| {
+            (
+                p.position_key.clone(),
+                PositionSessionTracker::new(p.clone(), FromDatabase),
+            )
+        })
+        .collect();
+
+    let mut active = ActiveSessionsMap {
+        controllers,
+        positions: HashMap::new(),
+        cooldown_controllers,
+        cooldown_positions,
+        last_full_flush: None,
+        merged_positions: vec![],
+        position_merges: vec![],
+        unmatched_controllers: vec![],
+        last_departures: db_get_last_departures(pool)
+            .await?
+            .into_iter()
+            .map(|d| (d.position_key, d.controller_session))
+            .collect(),
+        completed_positions: vec![],
+        completed_controllers: vec![],
+        handovers: vec![],
+        glitches: vec![],
+        datafeed_records: vec![],
+    };
+
+    // Inserted one by one so that duplicate open sessions for a position, e.g. left behind by
+    // earlier versions, are merged rather than one of them being dropped from tracking
+    for p in db_get_active_position_sessions(pool).await? {
+        active.insert_open_position(PositionSessionTracker::new(p, FromDatabase));
+    }
+
+    Ok(active)
+}
+
+// Ends the sessions missing from the tick and completes those whose cooldown ran out, keeping
+// everything that has to be written until the next save
+fn finish_tick(
+    active: &mut ActiveSessionsMap,
+    datafeed_timestamp: DateTime<Utc>,
+    vnas_positions: &MatcherIndex,
+    sessions_config: &SessionsConfig,
+    glitch_check: &GlitchCheck,
+) {
+    if !glitch_check.hold_sessions {
+        active.end_unmarked_sessions(datafeed_timestamp, |simple_callsign| {
+            sessions_config
+                .cooldown
+                .cooldown_for(simple_callsign, vnas_positions.artcc_for(simple_callsign))
+        });
+    }
+    // Only sessions seen in this tick count as tracked, so a tick without controllers records zero
+    let num_p = active
+        .positions
+        .values()
+        .filter(|p| p.marked_active)
+        .count() as i32;
+    let num_c = active
+        .controllers
+        .values()
+        .filter(|c| c.marked_active)
+        .count() as i32;
+    let (completed_positions, completed_controllers) = active.roll_over();
+    let handovers = active.find_handovers(
+        datafeed_timestamp,
+        &completed_positions,
+        &completed_controllers,
+    );
+    if !handovers.is_empty() {
+        counter!(POSITION_HANDOVERS).increment(handovers.len() as u64);
+    }
+
+    active.completed_positions.extend(completed_positions);
+    active.completed_controllers.extend(completed_controllers);
+    active.handovers.extend(handovers);
+    if let Some(glitch) = &glitch_check.glitch {
+        // A glitch over several unsaved ticks is only written as it stood in the last of them
+        match active.glitches.last_mut() {
+            Some(last) if last.id == glitch.id => *last = glitch.clone(),
+            _ => active.glitches.push(glitch.clone()),
+        }
+    }
+    active.datafeed_records.push(DatafeedRecord {
+        update: datafeed_timestamp,
+        num_tracked_controller_sessions: num_c,
+        num_tracked_position_sessions: num_p,
+    });
+    active.clear_marks();
+}
+
+async fn save_all_sessions(
+    pool: &Pool<Postgres>,
+    active: &mut ActiveSessionsMap,
+    sessions_config: &SessionsConfig,
+) -> Result<(), sqlx::Error> {
+    // Sessions are saved as they were after the last tick processed
+    let Some(datafeed_timestamp) = active.datafeed_records.last().map(|r| r.update) else {
+        return Ok(());
+    };
+
+    // Sessions that only moved on in time are written with everything else every flush interval,
+    // so the number written each save follows how many sessions changed rather than how many
+    // are tracked
+    let flush_interval = chrono::Duration::seconds(sessions_config.flush_interval_seconds.into());
+    let full_flush = active.full_flush_due(datafeed_timestamp, flush_interval);
+    let positions: Vec<&PositionSessionTracker> = active
+        .positions
+        .values()
+        .chain(active.cooldown_positions.values())
+        .filter(|p| full_flush || p.dirty)
+        .chain(active.completed_positions.iter())
+        .collect();
+    let controllers: Vec<&ControllerSessionTracker> = active
+        .controllers
+        .values()
+        .chain(active.cooldown_controllers.values())
+        .filter(|c| full_flush || c.dirty)
+        .chain(active.completed_controllers.iter())
+        .collect();
+    trace!(
+        full_flush,
+        num_ticks = active.datafeed_records.len(),
+        num_positions_written = positions.len(),
+        num_controllers_written = controllers.len(),
+        "Saving sessions"
+    );
+
+    // Everything is written in one transaction, so a failure part way through can't leave
+    // controller sessions pointing at position session state from a different tick
+    let mut tx = pool.begin().await?;
+    db_save_position_sessions(&mut tx, &positions).await?;
+    db_save_controller_sessions(&mut tx, &controllers).await?;
+    db_save_controller_session_segments(&mut tx, &controllers).await?;
+    db_repoint_controller_sessions(&mut tx, &active.position_merges).await?;
+    db_insert_position_handovers(&mut tx, &active.handovers).await?;
+    db_record_unmatched_controllers(&mut tx, &active.unmatched_controllers).await?;
+    for glitch in &active.glitches {
+        db_upsert_datafeed_glitch(&mut tx, glitch).await?;
+    }
+    db_insert_datafeed_records(&mut tx, &active.datafeed_records).await?;
+    tx.commit().await?;
+
+    active.mark_saved(datafeed_timestamp, full_flush);
+    Ok(())
+}
+
+// Keeps the position session under `position_key` going for a controller whose session carries on
+// from an earlier tick. The key can differ from the one the controller session is attached to,
+// e.g. after a frequency change or a reconnect to another split, in which case the position session
+// for the new key is resurrected or opened as needed and the controller session moves over to it
+fn follow_position(
+    active: &mut ActiveSessionsMap,
+    controller_key: &str,
+    position_key: &str,
+    datafeed_controller: &Controller,
+    datafeed_timestamp: DateTime<Utc>,
+    matches: Option<&[&PositionExt]>,
+) {
+    // Only the controller's own position session is resurrected, merging it with any session
+    // someone else has opened for the position in the meantime. Cooling down sessions left by
+    // other controllers are left to complete, as for new controllers
+    if active.own_position_is_cooling_down(controller_key, position_key) {
+        active.resurrect_position_from(position_key, datafeed_controller, datafeed_timestamp);
+    } else if active.position_exists(position_key) {
+        active.mark_position_active_from(position_key, datafeed_controller, datafeed_timestamp);
+    } else if let Some(new_position_session_tracker) = create_new_position_session_tracker(
+        datafeed_controller,
+        datafeed_timestamp,
+        matches,
+        position_key,
+    ) {
+        active.insert_new_position(new_position_session_tracker);
+        record_session_event(SessionKind::Position, SessionEvent::Opened);
+    }
+
+    active.attach_controller_to_position(controller_key, position_key);
+}
+
+fn create_new_controller_session_tracker(
+    datafeed_controller: &Controller,
+    datafeed_timestamp: DateTime<Utc>,
+    matches: Option<&[&PositionExt]>,
+    assoc_position: &PositionSession,
+) -> Option<ControllerSessionTracker> {
+    let assoc_vnas_positions: Option<Vec<VnasPositionInfo>> =
+        matches.map(|m| m.iter().copied().map(VnasPositionInfo::from).collect());
+    if assoc_vnas_positions.is_none() {
+        trace!(
+            callsign = datafeed_controller.callsign,
+            frequency = datafeed_controller.frequency,
+            "No vNAS position matched controller"
+        );
+        counter!(UNMATCHED_CONTROLLERS).increment(1);
+    }
+
+    if let (Ok(start_time), Ok(last_updated)) = (
+        DateTime::parse_from_rfc3339(&datafeed_controller.logon_time),
+        DateTime::parse_from_rfc3339(&datafeed_controller.last_updated),
+    ) {
+        let new_controller_session = ControllerSession {
+            id: Uuid::now_v7(),
+            start_time: start_time.to_utc(),
+            end_time: None,
+            last_updated: last_updated.to_utc(),
+            duration: interval_from(start_time.to_utc(), last_updated.to_utc()),
+            datafeed_first: datafeed_timestamp,
+            datafeed_last: datafeed_timestamp,
+            is_active: true,
+            cid: datafeed_controller.cid as i32,
+            position_simple_callsign: assoc_position.position_simple_callsign.to_owned(),
+            connected_callsign: datafeed_controller.callsign.to_owned(),
+            connected_frequency: datafeed_controller.frequency.to_owned(),
+            position_session_id: assoc_position.id,
+            position_session_is_active: assoc_position.is_active,
+            is_cooling_down: false,
+            last_logon_time: start_time.to_utc(),
+            disconnect_count: 0,
+        };
+
+        Some(ControllerSessionTracker {
+            segment: Some(ControllerSessionSegment::first_of(&new_controller_session)),
+            controller_session: new_controller_session,
+            marked_active: true,
+            assoc_vnas_positions,
+            source: NewlyCreated,
+            dirty: true,
+            ended_segments: vec![],
+            added_vnas_positions: vec![],
+            frequency_changed: false,
+            unmatched_checked_on: None,
+        })
+    } else {
+        warn!(
+            start_time = datafeed_controller.logon_time,
+            last_updated = datafeed_controller.last_updated,
+            "Could not parse time from strings"
+        );
+        None
+    }
+}
+
+fn create_new_position_session_tracker(
+    datafeed_controller: &Controller,
+    datafeed_timestamp: DateTime<Utc>,
+    matches: Option<&[&PositionExt]>,
+    position_key: &str,
+) -> Option<PositionSessionTracker> {
+    let facilities = if let Some(possible_positions) = matches {
+        let mut f = possible_positions.to_vec();
+        f.dedup_by_key(|p| p.parent_facility.id.as_str());
+        Some(f)
+    } else {
+        None
+    };
+
+    let assoc_vnas_facilities: Option<Vec<VnasFacilityInfo>> =
+        facilities.map(|f| f.into_iter().map(VnasFacilityInfo::from).collect());
+
+    if let (Ok(start_time), Ok(last_updated)) = (
+        DateTime::parse_from_rfc3339(&datafeed_controller.logon_time),
+        DateTime::parse_from_rfc3339(&datafeed_controller.last_updated),
+    ) {
+        let new_position_session = PositionSession {
+            id: Uuid::now_v7(),
+            start_time: start_time.to_utc(),
+            end_time: None,
+            last_updated: last_updated.to_utc(),
+            duration: interval_from(start_time.to_utc(), last_updated.to_utc()),
+            datafeed_first: datafeed_timestamp,
+            datafeed_last: datafeed_timestamp,
+            is_active: true,
+            position_simple_callsign: datafeed_controller.simple_callsign().to_owned(),
+            is_cooling_down: false,
+            position_key: position_key.to_owned(),
+        };
+
+        Some(PositionSessionTracker {
+            position_session: new_position_session,
+            marked_active: true,
+            assoc_vnas_facilities,
+            source: NewlyCreated,
+            dirty: true,
+        })
+    } else {
+        warn!(
+            start_time = datafeed_controller.logon_time,
+            last_updated = datafeed_controller.last_updated,
+            "Could not parse time from strings"
+        );
+        None
+    }
+}
+
+// Controllers that matched exactly one vNAS position need no diagnosis. For those that matched
+// none, the positions sharing their simple callsign are kept as candidates, since a wrong frequency
+// in the vNAS data is the usual cause
+fn find_unmatched_controller(
+    datafeed_controller: &Controller,
+    datafeed_timestamp: DateTime<Utc>,
+    matches: Option<&[&PositionExt]>,
+    vnas_positions: &MatcherIndex,
+) -> Option<UnmatchedController> {
+    let (num_matches, candidates) = match matches {
+        Some([_]) => return None,
+        Some(matches) => (matches.len(), matches.to_vec()),
+        None => (
+            0,
+            vnas_positions.positions_for(&datafeed_controller.simple_callsign()),
+        ),
+    };
+
+    Some(UnmatchedController {
+        callsign: datafeed_controller.callsign.to_owned(),
+        frequency: datafeed_controller.frequency.to_owned(),
+        num_matches: num_matches as i32,
+        candidate_positions: candidates.into_iter().map(VnasPositionInfo::from).collect(),
+        seen: datafeed_timestamp,
+    })
+}
+
+pub fn is_active_vnas_controller(c: &Controller) -> bool {
+    c.server == "VIRTUALNAS" && c.facility > 0 && c.frequency != "199.998"
+}
+
+fn make_controller_key(cid: &str, time: DateTime<Utc>) -> String {
+    format!("{} {}", cid, time.timestamp())
+}
+
+fn try_make_controller_key(c: &Controller) -> Option<String> {
+    if let Ok(parsed_time) = DateTime::parse_from_rfc3339(&c.logon_time) {
+        Some(format!("{} {}", c.cid, parsed_time.to_utc().timestamp()))
+    } else {
+        None
+    }
+}
+
+// Controllers that match no vNAS position, or several, fall back to their simple callsign even
+// when keying by vNAS position
+fn make_position_key(
+    c: &Controller,
+    matches: Option<&[&PositionExt]>,
+    identity: PositionIdentity,
+) -> String {
+    match identity {
+        PositionIdentity::SimpleCallsign => c.simple_callsign(),
+        PositionIdentity::VnasPosition => match matches {
+            Some([p]) => p.position.id.to_owned(),
+            _ => c.simple_callsign(),
+        },
+    }
+}
+
+fn interval_from(start: DateTime<Utc>, end: DateTime<Utc>) -> PgInterval {
+    let d = chrono::Duration::milliseconds((end - start).num_milliseconds());
+    PgInterval::try_from(d).expect("Error converting Duration to PgInterval")
+}
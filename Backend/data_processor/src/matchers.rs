@@ -1,7 +1,7 @@
-use crate::vnas::extended_models::PositionExt;
+use crate::vnas::extended_models::{Callsign, PositionExt};
+use std::collections::HashMap;
 use vatsim_utils::models::Controller;
 
-#[allow(dead_code)]
 pub fn single_or_no_match<'a>(
     matchers: &'a [PositionExt],
     controller: &Controller,
@@ -35,3 +35,73 @@ pub fn all_matches<'a>(
         Some(positions)
     }
 }
+
+// Positions grouped by frequency in Hz. A position can only match controllers on its own
+// frequency, so the regex only has to run against the handful of positions sharing it
+pub struct MatcherIndex {
+    positions: Vec<PositionExt>,
+    by_frequency: HashMap<i64, Vec<usize>>,
+    by_callsign: HashMap<String, Vec<usize>>,
+    artccs: HashMap<String, String>,
+}
+
+impl MatcherIndex {
+    pub fn new(positions: Vec<PositionExt>) -> MatcherIndex {
+        let mut by_frequency: HashMap<i64, Vec<usize>> = HashMap::new();
+        let mut by_callsign: HashMap<String, Vec<usize>> = HashMap::new();
+        let mut artccs = HashMap::new();
+        for (i, p) in positions.iter().enumerate() {
+            let simple_callsign = p.position.simple_callsign();
+            artccs
+                .entry(simple_callsign.clone())
+                .or_insert_with(|| p.artcc_id.clone());
+            by_callsign.entry(simple_callsign).or_default().push(i);
+            by_frequency
+                .entry(p.position.frequency)
+                .or_default()
+                .push(i);
+        }
+        MatcherIndex {
+            positions,
+            by_frequency,
+            by_callsign,
+            artccs,
+        }
+    }
+
+    pub fn positions(&self) -> &[PositionExt] {
+        &self.positions
+    }
+
+    // The ARTCC of the first position with this simple callsign, on any frequency
+    pub fn artcc_for(&self, simple_callsign: &str) -> Option<&str> {
+        self.artccs.get(simple_callsign).map(String::as_str)
+    }
+
+    // Every position with this simple callsign, on any frequency
+    pub fn positions_for(&self, simple_callsign: &str) -> Vec<&PositionExt> {
+        self.by_callsign
+            .get(simple_callsign)
+            .map(|ix| ix.iter().map(|&i| &self.positions[i]).collect())
+            .unwrap_or_default()
+    }
+
+    // Same results, in the same order, as `all_matches` over `positions()`
+    pub fn all_matches(&self, controller: &Controller) -> Option<Vec<&PositionExt>> {
+        let Ok(freq) = controller.frequency.parse::<f64>() else {
+            return None;
+        };
+        let positions: Vec<&PositionExt> = self
+            .by_frequency
+            .get(&((freq * 1e6).round() as i64))?
+            .iter()
+            .map(|&i| &self.positions[i])
+            .filter(|p| p.regex.is_match(&controller.callsign))
+            .collect();
+        if positions.is_empty() {
+            None
+        } else {
+            Some(positions)
+        }
+    }
+}
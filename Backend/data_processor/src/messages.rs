@@ -0,0 +1,29 @@
+use shared::envelope::{decode_payload, open, EnvelopeError, EnvelopeHeader};
+use shared::RedisControllersMsg;
+
+#[derive(Debug, thiserror::Error)]
+pub enum MessageDecodeError {
+    #[error("could not decode message envelope")]
+    Envelope(#[from] EnvelopeError),
+
+    #[error("schema version {0} is not supported by this processor")]
+    UnsupportedSchemaVersion(u16),
+}
+
+// Decodes a datafeed queue message written by any fetcher version this processor knows about, so
+// that fetchers and processors can be redeployed independently
+pub fn decode_datafeed_message(
+    bytes: &[u8],
+) -> Result<(EnvelopeHeader, RedisControllersMsg), MessageDecodeError> {
+    let (header, payload) = open(bytes)?;
+
+    let msg = match header.schema_version {
+        // Version 1 is the header-less deflated JSON format, and version 2 only added the envelope
+        // around the same struct. Version 3 added pilots, ATIS and general data, which decode as
+        // empty for the older versions
+        1..=3 => decode_payload(header.codec, payload)?,
+        other => return Err(MessageDecodeError::UnsupportedSchemaVersion(other)),
+    };
+
+    Ok((header, msg))
+}
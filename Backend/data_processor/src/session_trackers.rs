@@ -1,10 +1,17 @@
 use crate::database::models::{
-    ControllerSession, PositionSession, VnasFacilityInfo, VnasPositionInfo,
+    ControllerSession, ControllerSessionSegment, DatafeedGlitch, DatafeedRecord, PositionHandover,
+    PositionSession, UnmatchedController, VnasFacilityInfo, VnasPositionInfo,
 };
 use crate::make_controller_key;
-use chrono::{DateTime, Utc};
-use std::collections::HashMap;
+use crate::telemetry::{end_session_event, record_session_event, SessionEvent, SessionKind};
+use crate::vnas::extended_models::Callsign;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use tracing::info;
+use uuid::Uuid;
 use vatsim_utils::models::Controller;
+use ActiveSessionTrackerSource::FromDatabase;
 
 #[derive(PartialEq, Clone)]
 pub enum ActiveSessionTrackerSource {
@@ -18,6 +25,9 @@ pub struct PositionSessionTracker {
     pub marked_active: bool,
     pub assoc_vnas_facilities: Option<Vec<VnasFacilityInfo>>,
     pub source: ActiveSessionTrackerSource,
+    // Changed in a way that has to be written with the next save rather than waiting for the next
+    // full flush
+    pub dirty: bool,
 }
 
 impl PositionSessionTracker {
@@ -30,20 +40,40 @@ impl PositionSessionTracker {
             marked_active: false,
             assoc_vnas_facilities: None,
             source,
+            dirty: false,
         }
     }
 
     pub fn mark_active_from(&mut self, c: &Controller, datafeed_update: DateTime<Utc>) {
         self.marked_active = true;
+        let start_time = self.position_session.start_time;
+        self.dirty |= self.position_session.end_time.is_some();
         self.position_session.mark_active_from(c, datafeed_update);
+        // A controller that logged on earlier than anyone else on the position moves its start
+        self.dirty |= self.position_session.start_time != start_time;
     }
 
     pub fn end_session(
         &mut self,
         end_time: Option<DateTime<Utc>>,
-        datafeed_update: Option<DateTime<Utc>>,
+        datafeed_update: DateTime<Utc>,
+        cooldown: Duration,
     ) {
-        self.position_session.end_session(end_time, datafeed_update)
+        let (was_active, was_cooling_down) = (
+            self.position_session.is_active,
+            self.position_session.is_cooling_down,
+        );
+        self.position_session
+            .end_session(end_time, datafeed_update, cooldown);
+        if let Some(event) = end_session_event(
+            was_active,
+            was_cooling_down,
+            self.position_session.is_active,
+            self.position_session.is_cooling_down,
+        ) {
+            self.dirty = true;
+            record_session_event(SessionKind::Position, event);
+        }
     }
 }
 
@@ -53,6 +83,17 @@ pub struct ControllerSessionTracker {
     pub marked_active: bool,
     pub assoc_vnas_positions: Option<Vec<VnasPositionInfo>>,
     pub source: ActiveSessionTrackerSource,
+    // See `PositionSessionTracker::dirty`
+    pub dirty: bool,
+    // The open segment, and segments ended or positions matched since the last save
+    pub segment: Option<ControllerSessionSegment>,
+    pub ended_segments: Vec<ControllerSessionSegment>,
+    pub added_vnas_positions: Vec<VnasPositionInfo>,
+    // Set when the controller moved to another frequency, until they are matched again
+    pub frequency_changed: bool,
+    // UTC day the controller was last checked for exactly one vNAS match, so that an unmatched
+    // controller is recorded once for every day they are on
+    pub unmatched_checked_on: Option<NaiveDate>,
 }
 
 impl ControllerSessionTracker {
@@ -60,34 +101,146 @@ impl ControllerSessionTracker {
         controller_session: ControllerSession,
         source: ActiveSessionTrackerSource,
     ) -> ControllerSessionTracker {
+        // Every tick a session was seen in has already checked it for the day
+        let unmatched_checked_on = Some(controller_session.datafeed_last.date_naive());
         ControllerSessionTracker {
             controller_session,
             marked_active: false,
             assoc_vnas_positions: None,
             source,
+            dirty: false,
+            segment: None,
+            ended_segments: vec![],
+            added_vnas_positions: vec![],
+            frequency_changed: false,
+            unmatched_checked_on,
         }
     }
 
     pub fn mark_active_from(&mut self, c: &Controller, datafeed_update: DateTime<Utc>) {
         self.marked_active = true;
+        self.dirty |= self.controller_session.end_time.is_some();
         self.controller_session.mark_active_from(c, datafeed_update);
+        self.follow_connection(c, datafeed_update);
+    }
+
+    pub fn reconnect_from(&mut self, c: &Controller, datafeed_update: DateTime<Utc>) {
+        self.marked_active = true;
+        self.dirty = true;
+        self.controller_session.reconnect_from(c, datafeed_update);
+        self.follow_connection(c, datafeed_update);
+    }
+
+    // Starts a new segment when the controller shows up on a different frequency or callsign.
+    // Sessions from before segments were recorded get a first segment from the session itself
+    fn follow_connection(&mut self, c: &Controller, datafeed_update: DateTime<Utc>) {
+        let session = &self.controller_session;
+        let segment = self
+            .segment
+            .get_or_insert_with(|| ControllerSessionSegment::first_of(session));
+        if segment.frequency == c.frequency && segment.callsign == c.callsign {
+            segment.datafeed_last = datafeed_update;
+            return;
+        }
+
+        self.frequency_changed |= segment.frequency != c.frequency;
+        let mut ended = std::mem::replace(
+            segment,
+            ControllerSessionSegment::starting_at(session, c, datafeed_update),
+        );
+        ended.end_time = Some(datafeed_update);
+        self.ended_segments.push(ended);
+        self.dirty = true;
+    }
+
+    // Moves the session over to another position session, e.g. after the controller changed
+    // frequency to another split of their position
+    pub fn attach_to(&mut self, position: &PositionSession) {
+        if self.controller_session.position_session_id == position.id {
+            return;
+        }
+
+        info!(
+            cid = self.controller_session.cid,
+            from = %self.controller_session.position_session_id,
+            to = %position.id,
+            position_key = position.position_key,
+            "Moving controller session to another position session"
+        );
+        self.controller_session.position_session_id = position.id;
+        self.controller_session.position_session_is_active = position.is_active;
+        self.dirty = true;
+    }
+
+    // Records positions matched after the session started, e.g. on a new frequency
+    pub fn add_vnas_positions(&mut self, matched: Vec<VnasPositionInfo>) {
+        let assoc = self.assoc_vnas_positions.get_or_insert_with(Vec::new);
+        for p in matched {
+            if !assoc.iter().any(|a| a.id == p.id) {
+                assoc.push(p.clone());
+                self.added_vnas_positions.push(p);
+                self.dirty = true;
+            }
+        }
     }
 
     pub fn end_session(
         &mut self,
         end_time: Option<DateTime<Utc>>,
-        datafeed_update: Option<DateTime<Utc>>,
+        datafeed_update: DateTime<Utc>,
+        cooldown: Duration,
     ) {
+        let (was_active, was_cooling_down) = (
+            self.controller_session.is_active,
+            self.controller_session.is_cooling_down,
+        );
         self.controller_session
-            .end_session(end_time, datafeed_update)
+            .end_session(end_time, datafeed_update, cooldown);
+        if let Some(event) = end_session_event(
+            was_active,
+            was_cooling_down,
+            self.controller_session.is_active,
+            self.controller_session.is_cooling_down,
+        ) {
+            self.dirty = true;
+            record_session_event(SessionKind::Controller, event);
+        }
+
+        // The last segment ends with the session, once it has completed
+        if !self.controller_session.is_active {
+            if let Some(segment) = &mut self.segment {
+                segment.end_time = self.controller_session.end_time;
+            }
+        }
     }
 }
 
+// Kept in memory across ticks and only loaded from the database at startup
+#[derive(Clone)]
 pub struct ActiveSessionsMap {
     pub controllers: HashMap<String, ControllerSessionTracker>,
     pub positions: HashMap<String, PositionSessionTracker>,
     pub cooldown_controllers: HashMap<String, ControllerSessionTracker>,
     pub cooldown_positions: HashMap<String, PositionSessionTracker>,
+    // Datafeed time every tracked session was last written, whether or not it was dirty
+    pub last_full_flush: Option<DateTime<Utc>>,
+    // Position sessions completed by being merged into another, and the (duplicate id, survivor
+    // id) pairs the controller sessions in the database still have to be moved along
+    pub merged_positions: Vec<PositionSessionTracker>,
+    pub position_merges: Vec<(Uuid, Uuid)>,
+    // Controllers seen without exactly one vNAS position to go with them, for the first time today
+    // or on this frequency, still to be recorded for diagnosis
+    pub unmatched_controllers: Vec<UnmatchedController>,
+    // The completed controller session that left each position unstaffed, by position key, to be
+    // paired with whoever logs on to the position next however long that takes
+    pub last_departures: HashMap<String, ControllerSession>,
+    // Everything else from ticks processed since the last save, still to be written. While catching
+    // up on stale messages this covers several ticks
+    pub completed_positions: Vec<PositionSessionTracker>,
+    pub completed_controllers: Vec<ControllerSessionTracker>,
+    pub handovers: Vec<PositionHandover>,
+    pub glitches: Vec<DatafeedGlitch>,
+    pub datafeed_records: Vec<DatafeedRecord>,
 }
 
 impl ActiveSessionsMap {
@@ -95,7 +248,7 @@ impl ActiveSessionsMap {
         self.controllers.insert(
             make_controller_key(
                 &c.controller_session.cid.to_string(),
-                c.controller_session.start_time,
+                c.controller_session.last_logon_time,
             ),
             c,
         );
@@ -103,7 +256,54 @@ impl ActiveSessionsMap {
 
     pub fn insert_new_position(&mut self, p: PositionSessionTracker) {
         self.positions
-            .insert(p.position_session.position_simple_callsign.to_owned(), p);
+            .insert(p.position_session.position_key.to_owned(), p);
+    }
+
+    // Puts an open position session in place. If the position already has one, the two are merged
+    // into the one that started first: it keeps the earlier start, the other is completed, and
+    // the controller sessions attached to the other are moved over to it
+    pub fn insert_open_position(&mut self, p: PositionSessionTracker) {
+        let key = p.position_session.position_key.to_owned();
+        let Some(existing) = self.positions.remove(&key) else {
+            self.positions.insert(key, p);
+            return;
+        };
+
+        let (mut survivor, mut duplicate) =
+            if existing.position_session.start_time <= p.position_session.start_time {
+                (existing, p)
+            } else {
+                (p, existing)
+            };
+        let (survivor_id, duplicate_id) =
+            (survivor.position_session.id, duplicate.position_session.id);
+        info!(
+            key,
+            %survivor_id,
+            %duplicate_id,
+            "Merging duplicate position sessions"
+        );
+
+        survivor
+            .position_session
+            .absorb(&duplicate.position_session);
+        survivor.marked_active |= duplicate.marked_active;
+        survivor.dirty = true;
+        duplicate.position_session.end_as_merged();
+        duplicate.dirty = true;
+        record_session_event(SessionKind::Position, SessionEvent::Merged);
+
+        for c in self
+            .controllers
+            .values_mut()
+            .chain(self.cooldown_controllers.values_mut())
+            .filter(|c| c.controller_session.position_session_id == duplicate_id)
+        {
+            c.controller_session.position_session_id = survivor_id;
+        }
+        self.position_merges.push((duplicate_id, survivor_id));
+        self.merged_positions.push(duplicate);
+        self.positions.insert(key, survivor);
     }
 
     pub fn controller_exists(&self, key: &str) -> bool {
@@ -118,8 +318,14 @@ impl ActiveSessionsMap {
         self.positions.contains_key(key)
     }
 
-    pub fn cooldown_position_exists(&self, key: &str) -> bool {
-        self.cooldown_positions.contains_key(key)
+    // Whether the position session a controller is attached to is cooling down under `position_key`
+    pub fn own_position_is_cooling_down(&self, controller_key: &str, position_key: &str) -> bool {
+        let Some(c) = self.controllers.get(controller_key) else {
+            return false;
+        };
+        self.cooldown_positions
+            .get(position_key)
+            .is_some_and(|p| p.position_session.id == c.controller_session.position_session_id)
     }
 
     pub fn mark_controller_active_from(
@@ -141,12 +347,53 @@ impl ActiveSessionsMap {
     ) {
         if let Some(c) = self.cooldown_controllers.get_mut(key) {
             c.mark_active_from(controller, update);
+            record_session_event(SessionKind::Controller, SessionEvent::Resurrected);
             let new_c = c.clone();
             self.insert_new_controller(new_c);
             self.cooldown_controllers.remove(key);
         }
     }
 
+    // Key of the session a controller who logged on again would be reconnecting to: the latest
+    // one for the same CID and position that is cooling down, or that is still active but whose
+    // own connection isn't in this tick's datafeed
+    pub fn find_reconnected_controller(
+        &self,
+        controller: &Controller,
+        datafeed_keys: &HashSet<String>,
+    ) -> Option<String> {
+        let cid = controller.cid as i32;
+        let simple_callsign = controller.simple_callsign();
+        self.controllers
+            .iter()
+            .filter(|(key, c)| !c.marked_active && !datafeed_keys.contains(*key))
+            .chain(self.cooldown_controllers.iter())
+            .filter(|(_, c)| {
+                c.controller_session.cid == cid
+                    && c.controller_session.position_simple_callsign == simple_callsign
+            })
+            .max_by_key(|(_, c)| c.controller_session.last_logon_time)
+            .map(|(key, _)| key.to_owned())
+    }
+
+    // Moves the session under the key of the new connection, so later ticks find it directly
+    pub fn reconnect_controller_from(
+        &mut self,
+        key: &str,
+        controller: &Controller,
+        update: DateTime<Utc>,
+    ) {
+        if let Some(mut c) = self
+            .controllers
+            .remove(key)
+            .or_else(|| self.cooldown_controllers.remove(key))
+        {
+            c.reconnect_from(controller, update);
+            record_session_event(SessionKind::Controller, SessionEvent::Reconnected);
+            self.insert_new_controller(c);
+        }
+    }
+
     pub fn mark_position_active_from(
         &mut self,
         key: &str,
@@ -164,11 +411,21 @@ impl ActiveSessionsMap {
         controller: &Controller,
         update: DateTime<Utc>,
     ) {
-        if let Some(p) = self.cooldown_positions.get_mut(key) {
+        // A new session for the same position may have started while this one was cooling down,
+        // in which case the two are merged
+        if let Some(mut p) = self.cooldown_positions.remove(key) {
             p.mark_active_from(controller, update);
-            let new_p = p.clone();
-            self.insert_new_position(new_p); // TODO -- some kind of check if position already exists?
-            self.cooldown_positions.remove(key);
+            record_session_event(SessionKind::Position, SessionEvent::Resurrected);
+            self.insert_open_position(p);
+        }
+    }
+
+    pub fn attach_controller_to_position(&mut self, controller_key: &str, position_key: &str) {
+        if let (Some(c), Some(p)) = (
+            self.controllers.get_mut(controller_key),
+            self.positions.get(position_key),
+        ) {
+            c.attach_to(&p.position_session);
         }
     }
 
@@ -180,4 +437,858 @@ impl ActiveSessionsMap {
     pub fn get_controller(&self, key: &str) -> Option<&ControllerSessionTracker> {
         self.controllers.get(key)
     }
+
+    // `cooldown_for` gives the cooldown for a session from its position's simple callsign
+    pub fn end_unmarked_sessions(
+        &mut self,
+        datafeed_update: DateTime<Utc>,
+        cooldown_for: impl Fn(&str) -> Duration,
+    ) {
+        for p in self
+            .positions
+            .values_mut()
+            .chain(self.cooldown_positions.values_mut())
+            .filter(|p| !p.marked_active)
+        {
+            let cooldown = cooldown_for(&p.position_session.position_simple_callsign);
+            p.end_session(None, datafeed_update, cooldown);
+        }
+
+        for c in self
+            .controllers
+            .values_mut()
+            .chain(self.cooldown_controllers.values_mut())
+            .filter(|c| !c.marked_active)
+        {
+            let cooldown = cooldown_for(&c.controller_session.position_simple_callsign);
+            c.end_session(None, datafeed_update, cooldown);
+        }
+    }
+
+    // Moves sessions that started cooling down this tick into the cooldown maps, and takes out
+    // sessions that completed so they can be written one last time
+    pub fn roll_over(&mut self) -> (Vec<PositionSessionTracker>, Vec<ControllerSessionTracker>) {
+        let mut completed_positions = std::mem::take(&mut self.merged_positions);
+        let positions: Vec<_> = self
+            .positions
+            .drain()
+            .chain(self.cooldown_positions.drain())
+            .collect();
+        for (key, p) in positions {
+            let session = &p.position_session;
+            if !session.is_active {
+                completed_positions.push(p);
+            } else if !session.is_cooling_down {
+                self.positions.insert(key, p);
+            } else {
+                match self.cooldown_positions.entry(key) {
+                    Entry::Vacant(e) => {
+                        e.insert(p);
+                    }
+                    // Two sessions for the same position cooling down at once. Only the latest can
+                    // still be resurrected, so the other is completed now
+                    Entry::Occupied(mut e) => {
+                        let (mut older, newer) = if e.get().position_session.start_time
+                            > p.position_session.start_time
+                        {
+                            (p, e.get().clone())
+                        } else {
+                            (e.get().clone(), p)
+                        };
+                        older.position_session.is_active = false;
+                        older.position_session.is_cooling_down = false;
+                        older.dirty = true;
+                        record_session_event(SessionKind::Position, SessionEvent::Closed);
+                        completed_positions.push(older);
+                        e.insert(newer);
+                    }
+                }
+            }
+        }
+
+        let mut completed_controllers = vec![];
+        let controllers: Vec<_> = self
+            .controllers
+            .drain()
+            .chain(self.cooldown_controllers.drain())
+            .collect();
+        for (key, c) in controllers {
+            let session = &c.controller_session;
+            if !session.is_active {
+                completed_controllers.push(c);
+            } else if !session.is_cooling_down {
+                self.controllers.insert(key, c);
+            } else {
+                self.cooldown_controllers.insert(key, c);
+            }
+        }
+
+        (completed_positions, completed_controllers)
+    }
+
+    // Pairs each controller session that completed this tick with the controller who took over the
+    // position. Only completed sessions are considered, so a controller who reconnects within the
+    // cooldown is not taken for a handover. A session that leaves the position unstaffed is kept as
+    // its last departure until the next controller logs on, whatever the gap
+    pub fn find_handovers(
+        &mut self,
+        datafeed_update: DateTime<Utc>,
+        completed_positions: &[PositionSessionTracker],
+        completed_controllers: &[ControllerSessionTracker],
+    ) -> Vec<PositionHandover> {
+        let position_keys: HashMap<Uuid, &str> = self
+            .positions
+            .values()
+            .chain(self.cooldown_positions.values())
+            .chain(completed_positions)
+            .map(|p| {
+                (
+                    p.position_session.id,
+                    p.position_session.position_key.as_str(),
+                )
+            })
+            .collect();
+        let sessions_by_key: HashMap<&str, Vec<&ControllerSession>> = self
+            .controllers
+            .values()
+            .chain(self.cooldown_controllers.values())
+            .chain(completed_controllers)
+            .filter_map(|c| {
+                let key = position_keys.get(&c.controller_session.position_session_id)?;
+                Some((*key, &c.controller_session))
+            })
+            .fold(HashMap::new(), |mut m, (key, c)| {
+                m.entry(key).or_insert_with(Vec::new).push(c);
+                m
+            });
+
+        let mut handovers = vec![];
+        let mut departures = vec![];
+        for outgoing in completed_controllers.iter().map(|c| &c.controller_session) {
+            let Some(key) = position_keys.get(&outgoing.position_session_id) else {
+                continue;
+            };
+            let end_time = outgoing.end_time.unwrap_or(outgoing.last_updated);
+            let (staffing, later): (Vec<&ControllerSession>, Vec<&ControllerSession>) =
+                sessions_by_key
+                    .get(key)
+                    .map_or(&[][..], Vec::as_slice)
+                    .iter()
+                    .filter(|c| c.id != outgoing.id)
+                    .partition(|c| c.start_time <= end_time);
+            let staffing: Vec<_> = staffing
+                .into_iter()
+                .filter(|c| c.end_time.is_none_or(|e| e > end_time))
+                .collect();
+
+            let incoming = if staffing.is_empty() {
+                // The position was left unstaffed, so whoever logs on next takes over, unless it is
+                // the outgoing controller coming back
+                match later.into_iter().min_by_key(|c| c.start_time) {
+                    Some(next) => Some(next).filter(|c| c.cid != outgoing.cid),
+                    None => {
+                        departures.push((key.to_string(), outgoing.clone()));
+                        None
+                    }
+                }
+            } else {
+                // Someone is still on the position. Only a relief who joined the outgoing
+                // controller on their frequency took over, anyone else is working another split
+                // that shares the position key
+                staffing
+                    .into_iter()
+                    .filter(|c| {
+                        c.cid != outgoing.cid
+                            && c.start_time > outgoing.start_time
+                            && c.connected_frequency == outgoing.connected_frequency
+                    })
+                    .min_by_key(|c| c.start_time)
+            };
+            if let Some(incoming) = incoming {
+                handovers.push(PositionHandover::between(key, outgoing, incoming));
+            }
+        }
+
+        // New sessions this tick, earliest first so that the first to log on takes over
+        let mut logons: Vec<(String, ControllerSession)> = self
+            .controllers
+            .values()
+            .filter(|c| c.controller_session.datafeed_first == datafeed_update)
+            .filter_map(|c| {
+                let key = position_keys.get(&c.controller_session.position_session_id)?;
+                Some((key.to_string(), c.controller_session.clone()))
+            })
+            .collect();
+        logons.sort_by_key(|(_, c)| c.start_time);
+
+        for (key, outgoing) in departures {
+            if self
+                .last_departures
+                .get(&key)
+                .is_none_or(|d| d.end_time < outgoing.end_time)
+            {
+                self.last_departures.insert(key, outgoing);
+            }
+        }
+        for (key, incoming) in logons {
+            // A controller coming back to a position they left is not a handover
+            let Some(outgoing) = self.last_departures.remove(&key) else {
+                continue;
+            };
+            if outgoing.cid != incoming.cid {
+                handovers.push(PositionHandover::between(&key, &outgoing, &incoming));
+            }
+        }
+
+        handovers
+    }
+
+    pub fn full_flush_due(
+        &self,
+        datafeed_update: DateTime<Utc>,
+        interval: chrono::Duration,
+    ) -> bool {
+        self.last_full_flush
+            .is_none_or(|last| datafeed_update - last >= interval)
+    }
+
+    // Once a tick has been saved, every tracked session is in the database as it is in memory
+    // Gets every session ready for the next tick, which marks again those it still lists
+    pub fn clear_marks(&mut self) {
+        for p in self
+            .positions
+            .values_mut()
+            .chain(self.cooldown_positions.values_mut())
+        {
+            p.marked_active = false;
+        }
+        for c in self
+            .controllers
+            .values_mut()
+            .chain(self.cooldown_controllers.values_mut())
+        {
+            c.marked_active = false;
+        }
+    }
+
+    pub fn mark_saved(&mut self, datafeed_update: DateTime<Utc>, full_flush: bool) {
+        for p in self
+            .positions
+            .values_mut()
+            .chain(self.cooldown_positions.values_mut())
+        {
+            p.dirty = false;
+            p.source = FromDatabase;
+        }
+
+        for c in self
+            .controllers
+            .values_mut()
+            .chain(self.cooldown_controllers.values_mut())
+        {
+            c.dirty = false;
+            c.source = FromDatabase;
+            c.ended_segments.clear();
+            c.added_vnas_positions.clear();
+        }
+
+        self.completed_positions.clear();
+        self.completed_controllers.clear();
+        self.handovers.clear();
+        self.glitches.clear();
+        self.datafeed_records.clear();
+        self.position_merges.clear();
+        self.unmatched_controllers.clear();
+        if full_flush {
+            self.last_full_flush = Some(datafeed_update);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval_from;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    fn controller(cid: u64, callsign: &str, frequency: &str, logon_time: &str) -> Controller {
+        Controller {
+            cid,
+            callsign: callsign.to_owned(),
+            frequency: frequency.to_owned(),
+            logon_time: logon_time.to_owned(),
+            last_updated: logon_time.to_owned(),
+            ..Default::default()
+        }
+    }
+
+    // An open session as loaded from the database, i.e. saved and not yet marked this tick
+    fn position(key: &str, start_time: &str) -> PositionSessionTracker {
+        let start_time = at(start_time);
+        PositionSessionTracker::new(
+            PositionSession {
+                id: Uuid::now_v7(),
+                start_time,
+                end_time: None,
+                last_updated: start_time,
+                duration: interval_from(start_time, start_time),
+                datafeed_first: start_time,
+                datafeed_last: start_time,
+                is_active: true,
+                position_simple_callsign: key.to_owned(),
+                is_cooling_down: false,
+                position_key: key.to_owned(),
+            },
+            FromDatabase,
+        )
+    }
+
+    // An open session for `c`'s connection as loaded from the database
+    fn controller_session(c: &Controller, position_session_id: Uuid) -> ControllerSessionTracker {
+        let logon_time = at(&c.logon_time);
+        ControllerSessionTracker::new(
+            ControllerSession {
+                id: Uuid::now_v7(),
+                start_time: logon_time,
+                end_time: None,
+                last_updated: logon_time,
+                duration: interval_from(logon_time, logon_time),
+                datafeed_first: logon_time,
+                datafeed_last: logon_time,
+                is_active: true,
+                cid: c.cid as i32,
+                position_simple_callsign: c.simple_callsign().to_owned(),
+                connected_callsign: c.callsign.to_owned(),
+                connected_frequency: c.frequency.to_owned(),
+                position_session_id,
+                position_session_is_active: true,
+                is_cooling_down: false,
+                last_logon_time: logon_time,
+                disconnect_count: 0,
+            },
+            FromDatabase,
+        )
+    }
+
+    // A session for `c`'s connection that has ended and cooled down
+    fn completed_session(
+        c: &Controller,
+        position_session_id: Uuid,
+        end_time: &str,
+    ) -> ControllerSessionTracker {
+        let mut tracker = controller_session(c, position_session_id);
+        let session = &mut tracker.controller_session;
+        session.end_time = Some(at(end_time));
+        session.last_updated = at(end_time);
+        session.is_active = false;
+        tracker
+    }
+
+    fn key_of(c: &Controller) -> String {
+        make_controller_key(&c.cid.to_string(), at(&c.logon_time))
+    }
+
+    fn sessions() -> ActiveSessionsMap {
+        ActiveSessionsMap {
+            controllers: HashMap::new(),
+            positions: HashMap::new(),
+            cooldown_controllers: HashMap::new(),
+            cooldown_positions: HashMap::new(),
+            last_full_flush: None,
+            merged_positions: vec![],
+            position_merges: vec![],
+            unmatched_controllers: vec![],
+            last_departures: HashMap::new(),
+            completed_positions: vec![],
+            completed_controllers: vec![],
+            handovers: vec![],
+            glitches: vec![],
+            datafeed_records: vec![],
+        }
+    }
+
+    // Ends and rolls over every session not seen this tick, as finishing a tick does
+    fn end_tick(sessions: &mut ActiveSessionsMap, update: DateTime<Utc>) {
+        sessions.end_unmarked_sessions(update, |_| Duration::minutes(5));
+        sessions.roll_over();
+        sessions.clear_marks();
+    }
+
+    #[test]
+    fn earlier_logon_moves_position_start_and_needs_saving() {
+        let update = at("2024-07-21T13:00:15Z");
+        let mut p = position("BOS_CTR", "2024-07-21T12:00:00Z");
+
+        p.mark_active_from(
+            &controller(1, "BOS_CTR", "134.700", "2024-07-21T12:30:00Z"),
+            update,
+        );
+        assert_eq!(p.position_session.start_time, at("2024-07-21T12:00:00Z"));
+        assert!(!p.dirty);
+
+        p.mark_active_from(
+            &controller(2, "BOS_1_CTR", "134.700", "2024-07-21T11:45:00Z"),
+            update,
+        );
+        assert_eq!(p.position_session.start_time, at("2024-07-21T11:45:00Z"));
+        assert!(p.dirty);
+    }
+
+    #[test]
+    fn reconnect_within_cooldown_continues_session_under_new_key() {
+        let first = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut sessions = sessions();
+        let p = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        sessions.insert_new_controller(controller_session(&first, p.position_session.id));
+        sessions.insert_new_position(p);
+
+        // Dropped off the datafeed, so the session starts cooling down
+        end_tick(&mut sessions, at("2024-07-21T12:00:15Z"));
+        assert!(sessions.cooldown_controller_exists(&key_of(&first)));
+
+        let second = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:02:00Z");
+        let update = at("2024-07-21T12:02:15Z");
+        let datafeed_keys = HashSet::from([key_of(&second)]);
+        let key = sessions
+            .find_reconnected_controller(&second, &datafeed_keys)
+            .unwrap();
+        assert_eq!(key, key_of(&first));
+
+        sessions.reconnect_controller_from(&key, &second, update);
+        assert!(!sessions.cooldown_controller_exists(&key_of(&first)));
+        let c = sessions.get_controller(&key_of(&second)).unwrap();
+        assert!(c.marked_active && c.dirty);
+        assert_eq!(c.controller_session.start_time, at("2024-07-21T12:00:00Z"));
+        assert_eq!(
+            c.controller_session.last_logon_time,
+            at("2024-07-21T12:02:00Z")
+        );
+        assert_eq!(c.controller_session.disconnect_count, 1);
+        assert_eq!(c.controller_session.end_time, None);
+        assert!(!c.controller_session.is_cooling_down);
+    }
+
+    #[test]
+    fn reconnect_before_old_connection_is_ended() {
+        let first = controller(1, "BOS_1_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut sessions = sessions();
+        sessions.insert_new_controller(controller_session(&first, Uuid::now_v7()));
+
+        // Logged on again within the same tick the old connection disappeared, and on another
+        // split of the same position
+        let second = controller(1, "BOS_2_CTR", "134.700", "2024-07-21T12:30:00Z");
+        let key = sessions.find_reconnected_controller(&second, &HashSet::from([key_of(&second)]));
+        assert_eq!(key, Some(key_of(&first)));
+
+        // Both connections still in the datafeed, so neither is a reconnect of the other
+        let both = HashSet::from([key_of(&first), key_of(&second)]);
+        assert_eq!(sessions.find_reconnected_controller(&second, &both), None);
+    }
+
+    #[test]
+    fn reconnect_needs_same_cid_and_position() {
+        let first = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut sessions = sessions();
+        sessions.insert_new_controller(controller_session(&first, Uuid::now_v7()));
+        end_tick(&mut sessions, at("2024-07-21T12:00:15Z"));
+
+        for other in [
+            controller(2, "BOS_CTR", "134.700", "2024-07-21T12:02:00Z"),
+            controller(1, "BOS_APP", "134.700", "2024-07-21T12:02:00Z"),
+        ] {
+            let datafeed_keys = HashSet::from([key_of(&other)]);
+            assert_eq!(
+                sessions.find_reconnected_controller(&other, &datafeed_keys),
+                None
+            );
+        }
+    }
+
+    #[test]
+    fn no_reconnect_once_cooldown_has_passed() {
+        let first = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut sessions = sessions();
+        sessions.insert_new_controller(controller_session(&first, Uuid::now_v7()));
+        end_tick(&mut sessions, at("2024-07-21T12:00:15Z"));
+        end_tick(&mut sessions, at("2024-07-21T12:05:15Z"));
+        assert!(!sessions.cooldown_controller_exists(&key_of(&first)));
+
+        let second = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:06:00Z");
+        let datafeed_keys = HashSet::from([key_of(&second)]);
+        assert_eq!(
+            sessions.find_reconnected_controller(&second, &datafeed_keys),
+            None
+        );
+    }
+
+    #[test]
+    fn reconnect_picks_latest_candidate_and_skips_marked_sessions() {
+        let older = controller(1, "BOS_CTR", "134.700", "2024-07-21T11:00:00Z");
+        let newer = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut sessions = sessions();
+        sessions.insert_new_controller(controller_session(&older, Uuid::now_v7()));
+        sessions.insert_new_controller(controller_session(&newer, Uuid::now_v7()));
+
+        let again = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:30:00Z");
+        let datafeed_keys = HashSet::from([key_of(&again)]);
+        assert_eq!(
+            sessions.find_reconnected_controller(&again, &datafeed_keys),
+            Some(key_of(&newer))
+        );
+
+        // Already continued by another connection this tick
+        sessions.mark_controller_active_from(&key_of(&newer), &newer, at("2024-07-21T12:30:15Z"));
+        assert_eq!(
+            sessions.find_reconnected_controller(&again, &datafeed_keys),
+            Some(key_of(&older))
+        );
+    }
+
+    #[test]
+    fn open_position_merges_into_earlier_session_for_same_key() {
+        let mut sessions = sessions();
+        let earlier = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let mut later = position("BOS_CTR", "2024-07-21T12:20:00Z");
+        later.position_session.last_updated = at("2024-07-21T12:40:00Z");
+        later.marked_active = true;
+        let (earlier_id, later_id) = (earlier.position_session.id, later.position_session.id);
+
+        let on_later = controller(2, "BOS_CTR", "134.700", "2024-07-21T12:20:00Z");
+        sessions.insert_new_controller(controller_session(&on_later, later_id));
+        sessions.insert_new_position(earlier);
+        sessions.insert_open_position(later);
+
+        let survivor = sessions.get_position("BOS_CTR").unwrap();
+        assert_eq!(survivor.position_session.id, earlier_id);
+        assert_eq!(
+            survivor.position_session.start_time,
+            at("2024-07-21T12:00:00Z")
+        );
+        assert_eq!(
+            survivor.position_session.last_updated,
+            at("2024-07-21T12:40:00Z")
+        );
+        assert!(survivor.marked_active && survivor.dirty);
+
+        let [duplicate] = sessions.merged_positions.as_slice() else {
+            panic!("expected exactly one merged position");
+        };
+        assert_eq!(duplicate.position_session.id, later_id);
+        assert!(!duplicate.position_session.is_active);
+        assert_eq!(
+            duplicate.position_session.end_time,
+            Some(at("2024-07-21T12:40:00Z"))
+        );
+        assert_eq!(sessions.position_merges, [(later_id, earlier_id)]);
+        assert_eq!(
+            sessions
+                .get_controller(&key_of(&on_later))
+                .unwrap()
+                .controller_session
+                .position_session_id,
+            earlier_id
+        );
+    }
+
+    #[test]
+    fn open_position_that_started_first_survives() {
+        let mut sessions = sessions();
+        let existing = position("BOS_CTR", "2024-07-21T12:20:00Z");
+        let resurrected = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let (existing_id, resurrected_id) = (
+            existing.position_session.id,
+            resurrected.position_session.id,
+        );
+        sessions.insert_new_position(existing);
+        sessions.insert_open_position(resurrected);
+
+        assert_eq!(
+            sessions
+                .get_position("BOS_CTR")
+                .unwrap()
+                .position_session
+                .id,
+            resurrected_id
+        );
+        assert_eq!(sessions.position_merges, [(existing_id, resurrected_id)]);
+    }
+
+    #[test]
+    fn open_position_without_existing_session_is_inserted() {
+        let mut sessions = sessions();
+        sessions.insert_new_position(position("BOS_CTR", "2024-07-21T12:00:00Z"));
+        sessions.insert_open_position(position("BOS_APP", "2024-07-21T12:20:00Z"));
+
+        assert!(sessions.position_exists("BOS_CTR") && sessions.position_exists("BOS_APP"));
+        assert!(sessions.merged_positions.is_empty() && sessions.position_merges.is_empty());
+    }
+
+    #[test]
+    fn same_connection_extends_first_segment() {
+        let c = controller(1, "BOS_1_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut tracker = controller_session(&c, Uuid::now_v7());
+
+        tracker.mark_active_from(&c, at("2024-07-21T12:00:15Z"));
+        tracker.mark_active_from(&c, at("2024-07-21T12:00:30Z"));
+
+        let segment = tracker.segment.as_ref().unwrap();
+        assert_eq!(segment.controller_session_id, tracker.controller_session.id);
+        assert_eq!(segment.start_time, at("2024-07-21T12:00:00Z"));
+        assert_eq!(segment.datafeed_last, at("2024-07-21T12:00:30Z"));
+        assert_eq!(segment.end_time, None);
+        assert!(tracker.ended_segments.is_empty());
+        assert!(!tracker.dirty && !tracker.frequency_changed);
+    }
+
+    #[test]
+    fn frequency_change_starts_new_segment() {
+        let c = controller(1, "BOS_1_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut tracker = controller_session(&c, Uuid::now_v7());
+        tracker.mark_active_from(&c, at("2024-07-21T12:00:15Z"));
+
+        let update = at("2024-07-21T12:10:15Z");
+        let moved = Controller {
+            frequency: "128.350".to_owned(),
+            ..c.clone()
+        };
+        tracker.mark_active_from(&moved, update);
+
+        let [ended] = tracker.ended_segments.as_slice() else {
+            panic!("expected exactly one ended segment");
+        };
+        assert_eq!(ended.frequency, "134.700");
+        assert_eq!(ended.end_time, Some(update));
+        let segment = tracker.segment.as_ref().unwrap();
+        assert_eq!(segment.frequency, "128.350");
+        assert_eq!(segment.callsign, "BOS_1_CTR");
+        assert_eq!(segment.start_time, update);
+        assert_eq!(segment.end_time, None);
+        assert_ne!(segment.id, ended.id);
+        assert!(tracker.dirty && tracker.frequency_changed);
+    }
+
+    #[test]
+    fn callsign_change_starts_new_segment_on_same_frequency() {
+        let c = controller(1, "BOS_1_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut tracker = controller_session(&c, Uuid::now_v7());
+        tracker.mark_active_from(&c, at("2024-07-21T12:00:15Z"));
+
+        let update = at("2024-07-21T12:10:15Z");
+        let renamed = Controller {
+            callsign: "BOS_CTR".to_owned(),
+            ..c.clone()
+        };
+        tracker.mark_active_from(&renamed, update);
+
+        assert_eq!(tracker.ended_segments.len(), 1);
+        assert_eq!(tracker.ended_segments[0].callsign, "BOS_1_CTR");
+        assert_eq!(tracker.segment.as_ref().unwrap().callsign, "BOS_CTR");
+        // Matching only has to be redone for a new frequency
+        assert!(tracker.dirty && !tracker.frequency_changed);
+
+        // Saving clears what was ended, but not the open segment
+        let mut sessions = sessions();
+        sessions.insert_new_controller(tracker);
+        sessions.mark_saved(update, false);
+        let tracker = sessions.get_controller(&key_of(&c)).unwrap();
+        assert!(tracker.ended_segments.is_empty());
+        assert_eq!(tracker.segment.as_ref().unwrap().callsign, "BOS_CTR");
+    }
+
+    #[test]
+    fn unsaved_ticks_are_kept_until_saved() {
+        let mut sessions = sessions();
+        let staffed = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let staffed_id = staffed.position_session.id;
+        let staying = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut tracker = controller_session(&staying, staffed_id);
+        tracker.marked_active = true;
+        tracker.dirty = true;
+        sessions.insert_new_position(staffed);
+        sessions.insert_new_controller(tracker);
+        let left = controller(2, "BOS_1_CTR", "134.700", "2024-07-21T11:00:00Z");
+        let left = completed_session(&left, staffed_id, "2024-07-21T12:05:00Z");
+        sessions.completed_controllers.push(left);
+
+        // Finishing a tick, e.g. a stale one while catching up, only clears the marks
+        sessions.clear_marks();
+        let tracker = sessions.get_controller(&key_of(&staying)).unwrap();
+        assert!(!tracker.marked_active && tracker.dirty);
+        assert_eq!(sessions.completed_controllers.len(), 1);
+
+        sessions.mark_saved(at("2024-07-21T12:05:15Z"), false);
+        assert!(!sessions.get_controller(&key_of(&staying)).unwrap().dirty);
+        assert!(sessions.completed_controllers.is_empty());
+    }
+
+    #[test]
+    fn completed_session_closes_open_segment() {
+        let c = controller(1, "BOS_1_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let mut tracker = controller_session(&c, Uuid::now_v7());
+        let last_seen = Controller {
+            last_updated: "2024-07-21T12:20:00Z".to_owned(),
+            ..c.clone()
+        };
+        tracker.mark_active_from(&last_seen, at("2024-07-21T12:20:15Z"));
+
+        // Cooling down leaves the segment open, as the controller may still come back
+        tracker.end_session(None, at("2024-07-21T12:20:30Z"), Duration::minutes(5));
+        assert!(tracker.controller_session.is_cooling_down);
+        assert_eq!(tracker.segment.as_ref().unwrap().end_time, None);
+
+        tracker.end_session(None, at("2024-07-21T12:25:30Z"), Duration::minutes(5));
+        assert!(!tracker.controller_session.is_active);
+        assert_eq!(
+            tracker.segment.as_ref().unwrap().end_time,
+            Some(at("2024-07-21T12:20:00Z"))
+        );
+    }
+
+    #[test]
+    fn handover_pairs_next_logon_however_long_the_gap() {
+        let mut sessions = sessions();
+        let left = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let outgoing = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let outgoing =
+            completed_session(&outgoing, left.position_session.id, "2024-07-21T12:30:00Z");
+        let outgoing_id = outgoing.controller_session.id;
+        let update = at("2024-07-21T12:30:15Z");
+        assert!(sessions
+            .find_handovers(update, &[left], &[outgoing])
+            .is_empty());
+        assert!(sessions.last_departures.contains_key("BOS_CTR"));
+
+        // Hours later, on a new position session
+        let staffed = position("BOS_CTR", "2024-07-21T15:00:00Z");
+        let incoming = controller(2, "BOS_CTR", "134.700", "2024-07-21T15:00:00Z");
+        let incoming = controller_session(&incoming, staffed.position_session.id);
+        let incoming_id = incoming.controller_session.id;
+        sessions.insert_new_position(staffed);
+        sessions.insert_new_controller(incoming);
+
+        let handovers = sessions.find_handovers(at("2024-07-21T15:00:00Z"), &[], &[]);
+        assert_eq!(handovers.len(), 1);
+        let handover = &handovers[0];
+        assert_eq!(handover.outgoing_controller_session_id, outgoing_id);
+        assert_eq!(handover.incoming_controller_session_id, incoming_id);
+        assert_eq!(
+            handover.overlap,
+            interval_from(at("2024-07-21T15:00:00Z"), at("2024-07-21T12:30:00Z"))
+        );
+        assert!(sessions.last_departures.is_empty());
+
+        // Only paired once, in the tick the incoming controller logged on
+        assert!(sessions
+            .find_handovers(at("2024-07-21T15:00:15Z"), &[], &[])
+            .is_empty());
+    }
+
+    #[test]
+    fn controller_returning_to_position_is_no_handover() {
+        let mut sessions = sessions();
+        let left = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let first = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let first = completed_session(&first, left.position_session.id, "2024-07-21T12:30:00Z");
+        sessions.find_handovers(at("2024-07-21T12:30:15Z"), &[left], &[first]);
+
+        let staffed = position("BOS_CTR", "2024-07-21T15:00:00Z");
+        let back = controller(1, "BOS_CTR", "134.700", "2024-07-21T15:00:00Z");
+        let back = controller_session(&back, staffed.position_session.id);
+        sessions.insert_new_position(staffed);
+        sessions.insert_new_controller(back);
+
+        assert!(sessions
+            .find_handovers(at("2024-07-21T15:00:00Z"), &[], &[])
+            .is_empty());
+        assert!(sessions.last_departures.is_empty());
+    }
+
+    #[test]
+    fn position_still_staffed_is_no_departure() {
+        let mut sessions = sessions();
+        let staffed = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let staffed_id = staffed.position_session.id;
+        let staying = controller(2, "BOS_CTR", "134.700", "2024-07-21T11:50:00Z");
+        sessions.insert_new_controller(controller_session(&staying, staffed_id));
+        sessions.insert_new_position(staffed);
+
+        let leaving = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let leaving = completed_session(&leaving, staffed_id, "2024-07-21T12:30:00Z");
+        assert!(sessions
+            .find_handovers(at("2024-07-21T12:30:15Z"), &[], &[leaving])
+            .is_empty());
+        assert!(sessions.last_departures.is_empty());
+    }
+
+    #[test]
+    fn relief_on_same_frequency_is_handover_with_overlap() {
+        let mut sessions = sessions();
+        let staffed = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let staffed_id = staffed.position_session.id;
+        let relief = controller(2, "BOS_1_CTR", "134.700", "2024-07-21T12:55:00Z");
+        sessions.insert_new_controller(controller_session(&relief, staffed_id));
+        sessions.insert_new_position(staffed);
+
+        let relieved = controller(1, "BOS_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let relieved = completed_session(&relieved, staffed_id, "2024-07-21T13:00:00Z");
+        let handovers = sessions.find_handovers(at("2024-07-21T13:00:15Z"), &[], &[relieved]);
+        assert_eq!(handovers.len(), 1);
+        assert_eq!(handovers[0].incoming_cid, 2);
+        assert_eq!(
+            handovers[0].overlap,
+            interval_from(at("2024-07-21T12:55:00Z"), at("2024-07-21T13:00:00Z"))
+        );
+        assert!(sessions.last_departures.is_empty());
+    }
+
+    #[test]
+    fn concurrent_split_sector_is_no_handover() {
+        let mut sessions = sessions();
+        let staffed = position("BOS_CTR", "2024-07-21T12:00:00Z");
+        let staffed_id = staffed.position_session.id;
+        let split_2 = controller(2, "BOS_2_CTR", "135.500", "2024-07-21T12:30:00Z");
+        sessions.insert_new_controller(controller_session(&split_2, staffed_id));
+        sessions.insert_new_position(staffed);
+
+        // BOS_1_CTR leaves while BOS_2_CTR, sharing the simple callsign, carries on
+        let split_1 = controller(1, "BOS_1_CTR", "134.700", "2024-07-21T12:00:00Z");
+        let split_1 = completed_session(&split_1, staffed_id, "2024-07-21T13:00:00Z");
+        assert!(sessions
+            .find_handovers(at("2024-07-21T13:00:15Z"), &[], &[split_1])
+            .is_empty());
+        assert!(sessions.last_departures.is_empty());
+
+        // Once BOS_2_CTR leaves too, the next controller on the position takes over from them
+        let split_2 = completed_session(&split_2, staffed_id, "2024-07-21T14:00:00Z");
+        sessions.controllers.clear();
+        assert!(sessions
+            .find_handovers(at("2024-07-21T14:00:15Z"), &[], &[split_2])
+            .is_empty());
+        assert_eq!(sessions.last_departures["BOS_CTR"].cid, 2);
+    }
+
+    #[test]
+    fn controller_moves_to_position_under_new_key() {
+        let mut sessions = sessions();
+        let split_1 = position("P1", "2024-07-21T12:00:00Z");
+        let split_2 = position("P2", "2024-07-21T12:10:00Z");
+        let split_2_id = split_2.position_session.id;
+        let c = controller(1, "BOS_1_CTR", "134.700", "2024-07-21T12:00:00Z");
+        sessions.insert_new_controller(controller_session(&c, split_1.position_session.id));
+        sessions.insert_new_position(split_1);
+        sessions.insert_new_position(split_2);
+
+        // Same key, nothing to do
+        sessions.attach_controller_to_position(&key_of(&c), "P1");
+        assert!(!sessions.get_controller(&key_of(&c)).unwrap().dirty);
+
+        sessions.attach_controller_to_position(&key_of(&c), "P2");
+        let tracker = sessions.get_controller(&key_of(&c)).unwrap();
+        assert_eq!(tracker.controller_session.position_session_id, split_2_id);
+        assert!(tracker.controller_session.position_session_is_active);
+        assert!(tracker.dirty);
+    }
 }
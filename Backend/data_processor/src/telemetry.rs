@@ -0,0 +1,120 @@
+use metrics::{counter, describe_counter, describe_gauge, describe_histogram, Unit};
+
+pub const PROCESSING_DURATION: &str = "processor_datafeed_processing_duration_seconds";
+pub const QUEUE_LAG: &str = "processor_queue_lag_seconds";
+pub const SESSION_EVENTS: &str = "processor_session_events_total";
+pub const UNMATCHED_CONTROLLERS: &str = "processor_unmatched_controllers_total";
+pub const VNAS_REFRESHES: &str = "processor_vnas_refreshes_total";
+pub const RECEIVE_FAILURES: &str = "processor_receive_failures_total";
+pub const DECODE_FAILURES: &str = "processor_decode_failures_total";
+pub const DROPPED_MESSAGES: &str = "processor_dropped_messages_total";
+pub const STALE_MESSAGES: &str = "processor_stale_messages_total";
+pub const DATAFEED_GAPS: &str = "processor_datafeed_gaps_total";
+pub const DATAFEED_GLITCHES: &str = "processor_datafeed_glitches_total";
+pub const POSITION_HANDOVERS: &str = "processor_position_handovers_total";
+
+// Names of the checks reported on `/readyz`
+pub const READY_DATABASE: &str = "database";
+pub const READY_TRANSPORT: &str = "transport";
+pub const READY_MATCHERS: &str = "matchers";
+
+#[derive(Clone, Copy)]
+pub enum SessionKind {
+    Controller,
+    Position,
+}
+
+#[derive(Clone, Copy)]
+pub enum SessionEvent {
+    Opened,
+    Closed,
+    CooledDown,
+    Resurrected,
+    Reconnected,
+    Merged,
+}
+
+pub fn describe_metrics() {
+    describe_histogram!(
+        PROCESSING_DURATION,
+        Unit::Seconds,
+        "Time taken to process the controllers in one datafeed message and save their sessions"
+    );
+    describe_gauge!(
+        QUEUE_LAG,
+        Unit::Seconds,
+        "Time between the datafeed update of the last received message and when it was received"
+    );
+    describe_counter!(
+        SESSION_EVENTS,
+        "Session state changes, labelled by session kind and event"
+    );
+    describe_counter!(
+        UNMATCHED_CONTROLLERS,
+        "New controller sessions that matched no vNAS position"
+    );
+    describe_counter!(
+        VNAS_REFRESHES,
+        "Checks for new vNAS data, labelled by outcome"
+    );
+    describe_counter!(
+        RECEIVE_FAILURES,
+        "Errors receiving messages from the datafeed queue"
+    );
+    describe_counter!(
+        DECODE_FAILURES,
+        "Messages from the datafeed queue that could not be decoded"
+    );
+    describe_counter!(
+        DROPPED_MESSAGES,
+        "Messages acknowledged without processing after failing to decode too many times"
+    );
+    describe_counter!(
+        STALE_MESSAGES,
+        "Messages older than the stale threshold, labelled by the policy applied to them"
+    );
+    describe_counter!(
+        DATAFEED_GAPS,
+        "Runs of skipped stale messages recorded as gaps in the datafeed"
+    );
+    describe_counter!(
+        DATAFEED_GLITCHES,
+        "Suspected datafeed glitches, where the vNAS controller count dropped sharply in one tick"
+    );
+    describe_counter!(
+        POSITION_HANDOVERS,
+        "Controllers taking over a position from another controller"
+    );
+}
+
+pub fn record_session_event(kind: SessionKind, event: SessionEvent) {
+    let kind = match kind {
+        SessionKind::Controller => "controller",
+        SessionKind::Position => "position",
+    };
+    let event = match event {
+        SessionEvent::Opened => "opened",
+        SessionEvent::Closed => "closed",
+        SessionEvent::CooledDown => "cooled_down",
+        SessionEvent::Resurrected => "resurrected",
+        SessionEvent::Reconnected => "reconnected",
+        SessionEvent::Merged => "merged",
+    };
+    counter!(SESSION_EVENTS, "kind" => kind, "event" => event).increment(1);
+}
+
+// Classifies the state change made by ending a session given its flags before and after
+pub fn end_session_event(
+    was_active: bool,
+    was_cooling_down: bool,
+    is_active: bool,
+    is_cooling_down: bool,
+) -> Option<SessionEvent> {
+    if was_active && !is_active {
+        Some(SessionEvent::Closed)
+    } else if !was_cooling_down && is_cooling_down {
+        Some(SessionEvent::CooledDown)
+    } else {
+        None
+    }
+}
@@ -1,11 +1,10 @@
 use super::api_dtos::{ArtccRoot, Facility, Position};
 use regex::{Error, Regex};
 use std::num::ParseFloatError;
+use tracing::trace;
 use vatsim_utils::models::Controller;
 
 pub trait AllFacilities {
-    fn all_facilities(&self) -> Vec<Facility>;
-
     fn all_facilities_with_info(
         &self,
         artcc: &ArtccRoot,
@@ -14,7 +13,6 @@ pub trait AllFacilities {
 }
 
 pub trait AllPositions {
-    fn all_positions(&self) -> Vec<Position>;
     fn all_positions_with_parents(&self) -> Vec<PositionExt>;
 }
 
@@ -31,18 +29,6 @@ impl ArtccRoot {
 }
 
 impl AllFacilities for Facility {
-    fn all_facilities(&self) -> Vec<Facility> {
-        if self.child_facilities.is_empty() {
-            vec![self.to_owned()]
-        } else {
-            let mut vec = vec![self.to_owned()];
-            self.child_facilities
-                .iter()
-                .for_each(|f| vec.extend(f.all_facilities()));
-            vec
-        }
-    }
-
     fn all_facilities_with_info(
         &self,
         artcc: &ArtccRoot,
@@ -66,46 +52,34 @@ impl AllFacilities for Facility {
 }
 
 impl AllPositions for ArtccRoot {
-    fn all_positions(&self) -> Vec<Position> {
-        self.facility.all_positions()
-    }
     fn all_positions_with_parents(&self) -> Vec<PositionExt> {
         self.facility.all_positions_with_parents()
     }
 }
 
 impl AllPositions for Facility {
-    fn all_positions(&self) -> Vec<Position> {
-        if self.child_facilities.is_empty() {
-            self.positions.to_owned()
-        } else {
-            let mut vec = self.positions.to_owned();
-            self.child_facilities
-                .iter()
-                .for_each(|f| vec.extend(f.all_positions()));
-            vec
-        }
-    }
-
+    // Taken to be the top-level facility of an ARTCC, whose id is the ARTCC's id
     fn all_positions_with_parents(&self) -> Vec<PositionExt> {
-        if self.child_facilities.is_empty() {
-            map_positions_with_parent(self)
-        } else {
-            let mut vec = map_positions_with_parent(self);
-            self.child_facilities
-                .iter()
-                .for_each(|f| vec.extend(f.all_positions_with_parents()));
-            vec
-        }
+        positions_with_parents(self, &self.id)
     }
 }
 
-fn map_positions_with_parent(facility: &Facility) -> Vec<PositionExt> {
+fn positions_with_parents(facility: &Facility, artcc_id: &str) -> Vec<PositionExt> {
+    let mut vec = map_positions_with_parent(facility, artcc_id);
+    facility
+        .child_facilities
+        .iter()
+        .for_each(|f| vec.extend(positions_with_parents(f, artcc_id)));
+    vec
+}
+
+fn map_positions_with_parent(facility: &Facility, artcc_id: &str) -> Vec<PositionExt> {
     facility
         .positions
         .iter()
         .map(|p| PositionExt {
             parent_facility: facility.clone(),
+            artcc_id: artcc_id.to_owned(),
             position: p.clone(),
             regex: p.build_match_regex().unwrap(),
         })
@@ -114,6 +88,7 @@ fn map_positions_with_parent(facility: &Facility) -> Vec<PositionExt> {
 
 pub struct PositionExt {
     pub parent_facility: Facility,
+    pub artcc_id: String,
     pub position: Position,
     pub regex: Regex,
 }
@@ -124,12 +99,15 @@ impl PositionExt {
             && if let Ok(b) = self.is_freq_match(&controller.frequency) {
                 b
             } else {
-                dbg!("Error parsing VATSIM freq {}", &controller.frequency);
+                trace!(
+                    frequency = controller.frequency,
+                    "Error parsing VATSIM frequency"
+                );
                 false
             }
     }
 
-    fn is_freq_match(&self, vatsim_freq_str: &str) -> Result<bool, ParseFloatError> {
+    pub fn is_freq_match(&self, vatsim_freq_str: &str) -> Result<bool, ParseFloatError> {
         let vatsim_freq_f = vatsim_freq_str.parse::<f64>();
         if let Ok(f) = vatsim_freq_f {
             let vatsim_freq_i64 = (f * 1e6).round() as i64;
@@ -145,7 +123,6 @@ pub trait Callsign {
     fn callsign_infix(&self) -> Option<&str>;
     fn callsign_suffix(&self) -> &str;
     fn simple_callsign(&self) -> String;
-    fn is_match_for(&self, callsign: &str) -> bool;
     fn build_match_regex(&self) -> Result<Regex, Error>;
 }
 
@@ -175,10 +152,8 @@ impl Callsign for Position {
         format!("{}_{}", self.callsign_prefix(), self.callsign_suffix())
     }
 
-    fn is_match_for(&self, callsign: &str) -> bool {
-        self.build_match_regex().unwrap().is_match(callsign)
-    }
-
+    // Anchored so that only callsigns with this exact prefix and suffix match, e.g. NY_CTR must
+    // not match ZNY_CTR
     fn build_match_regex(&self) -> Result<Regex, Error> {
         let prefix_str = self.callsign_prefix();
         let infix_re = match self.callsign_infix() {
@@ -186,7 +161,7 @@ impl Callsign for Position {
             None => r"([1-9]_)?".to_owned(),
         };
         let suffix_str = self.callsign_suffix();
-        Regex::new(format!("{prefix_str}_{infix_re}{suffix_str}").as_str())
+        Regex::new(format!("^{prefix_str}_{infix_re}{suffix_str}$").as_str())
     }
 }
 
@@ -216,10 +191,6 @@ impl Callsign for Controller {
         format!("{}_{}", self.callsign_prefix(), self.callsign_suffix())
     }
 
-    fn is_match_for(&self, callsign: &str) -> bool {
-        self.build_match_regex().unwrap().is_match(callsign)
-    }
-
     fn build_match_regex(&self) -> Result<Regex, Error> {
         let prefix_str = self.callsign_prefix();
         let infix_re = match self.callsign_infix() {
@@ -227,6 +198,6 @@ impl Callsign for Controller {
             None => r"([1-9]_)?".to_owned(),
         };
         let suffix_str = self.callsign_suffix();
-        Regex::new(format!("{prefix_str}_{infix_re}{suffix_str}").as_str())
+        Regex::new(format!("^{prefix_str}_{infix_re}{suffix_str}$").as_str())
     }
 }
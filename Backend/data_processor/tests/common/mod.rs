@@ -0,0 +1,148 @@
+// Synthetic vNAS facilities and datafeed controllers for exercising the position matchers. Uses a
+// fixed-seed generator so failures are reproducible
+use data_processor::vnas::api_dtos::{Facility, FacilityType, Position};
+use data_processor::vnas::extended_models::{AllPositions, PositionExt};
+use vatsim_utils::models::Controller;
+
+const PREFIXES: [&str; 12] = [
+    "NY", "ZNY", "JFK", "LGA", "EWR", "BOS", "ZBW", "PHL", "DCA", "IAD", "ZDC", "N90",
+];
+const INFIXES: [&str; 6] = ["N", "S", "E1", "1", "APP", "FW"];
+const SUFFIXES: [&str; 7] = ["DEL", "GND", "TWR", "APP", "DEP", "CTR", "FSS"];
+
+// Few enough frequencies that positions sharing a prefix and suffix also share frequencies
+const NUM_FREQUENCIES: u64 = 40;
+
+pub struct Lcg(u64);
+
+impl Lcg {
+    pub fn new(seed: u64) -> Lcg {
+        Lcg(seed)
+    }
+
+    pub fn next(&mut self, bound: u64) -> u64 {
+        self.0 = self
+            .0
+            .wrapping_mul(6364136223846793005)
+            .wrapping_add(1442695040888963407);
+        (self.0 >> 33) % bound
+    }
+
+    fn pick<'a>(&mut self, items: &[&'a str]) -> &'a str {
+        items[self.next(items.len() as u64) as usize]
+    }
+}
+
+fn frequency_hz(rng: &mut Lcg) -> i64 {
+    118_000_000 + 25_000 * rng.next(NUM_FREQUENCIES) as i64
+}
+
+fn position(rng: &mut Lcg, id: usize) -> Position {
+    let prefix = rng.pick(&PREFIXES);
+    let suffix = rng.pick(&SUFFIXES);
+    let callsign = match rng.next(4) {
+        0 | 1 => format!("{prefix}_{suffix}"),
+        2 => format!("{prefix}_{}_{suffix}", rng.pick(&INFIXES)),
+        _ => format!("{prefix}_{}_X_{suffix}", rng.pick(&INFIXES)),
+    };
+    Position {
+        id: format!("P{id:05}"),
+        name: callsign.clone(),
+        starred: rng.next(2) == 0,
+        radio_name: callsign.clone(),
+        callsign,
+        frequency: frequency_hz(rng),
+        ..Default::default()
+    }
+}
+
+fn facility(rng: &mut Lcg, id: &str, depth: u32, next_position: &mut usize) -> Facility {
+    let num_positions = 1 + rng.next(8) as usize;
+    let positions = (0..num_positions)
+        .map(|_| {
+            *next_position += 1;
+            position(rng, *next_position)
+        })
+        .collect();
+    let child_facilities = if depth == 0 {
+        vec![]
+    } else {
+        (0..rng.next(4))
+            .map(|i| facility(rng, &format!("{id}{i}"), depth - 1, next_position))
+            .collect()
+    };
+    Facility {
+        id: id.to_string(),
+        type_field: if depth == 0 {
+            FacilityType::Atct
+        } else {
+            FacilityType::Tracon
+        },
+        name: id.to_string(),
+        child_facilities,
+        eram_configuration: None,
+        stars_configuration: None,
+        tower_cab_configuration: None,
+        asdex_configuration: None,
+        tdls_configuration: None,
+        flight_strips_configuration: None,
+        positions,
+        neighboring_facility_ids: vec![],
+        non_nas_facility_ids: vec![],
+    }
+}
+
+// Roughly `num_artccs` * 25 positions, in the same order the processor builds them in
+pub fn positions(rng: &mut Lcg, num_artccs: usize) -> Vec<PositionExt> {
+    let mut next_position = 0;
+    (0..num_artccs)
+        .flat_map(|i| {
+            facility(rng, &format!("Z{i:02}"), 2, &mut next_position).all_positions_with_parents()
+        })
+        .collect()
+}
+
+fn controller(callsign: String, frequency: String) -> Controller {
+    Controller {
+        callsign,
+        frequency,
+        server: "VIRTUALNAS".to_string(),
+        facility: 5,
+        ..Default::default()
+    }
+}
+
+// Controllers built around the given positions, covering callsigns with and without infixes,
+// near misses on the prefix or suffix, and frequencies that are off or unparseable
+pub fn controllers(rng: &mut Lcg, positions: &[PositionExt], num: usize) -> Vec<Controller> {
+    (0..num)
+        .map(|_| {
+            let p = &positions[rng.next(positions.len() as u64) as usize].position;
+            let parts: Vec<&str> = p.callsign.split('_').collect();
+            let prefix = parts[0];
+            let suffix = parts[parts.len() - 1];
+            let infix = rng.pick(&INFIXES);
+            let digit = 1 + rng.next(9);
+            let callsign = match rng.next(10) {
+                0 => p.callsign.clone(),
+                1 => format!("{prefix}_{suffix}"),
+                2 => format!("{prefix}_{digit}_{suffix}"),
+                3 => format!("{prefix}_{infix}_{suffix}"),
+                4 => format!("{prefix}_{infix}{digit}_{suffix}"),
+                5 => format!("{prefix}_{digit}{digit}_{suffix}"),
+                6 => format!("Z{prefix}_{suffix}"),
+                7 => format!("{prefix}_{suffix}_{digit}"),
+                8 => format!("{}_{}", rng.pick(&PREFIXES), rng.pick(&SUFFIXES)),
+                _ => prefix.to_string(),
+            };
+            let frequency = match rng.next(10) {
+                0 => format!("{:.3}", frequency_hz(rng) as f64 / 1e6),
+                1 => "199.998".to_string(),
+                2 => "not a frequency".to_string(),
+                3 => format!("{}", p.frequency as f64 / 1e6),
+                _ => format!("{:.3}", p.frequency as f64 / 1e6),
+            };
+            controller(callsign, frequency)
+        })
+        .collect()
+}
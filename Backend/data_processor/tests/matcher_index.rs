@@ -0,0 +1,78 @@
+mod common;
+
+use common::{controllers, positions, Lcg};
+use data_processor::matchers::{all_matches, MatcherIndex};
+use data_processor::vnas::api_dtos::Position;
+use data_processor::vnas::extended_models::{Callsign, PositionExt};
+
+fn ids(matches: Option<Vec<&PositionExt>>) -> Option<Vec<&str>> {
+    matches.map(|m| m.into_iter().map(|p| p.position.id.as_str()).collect())
+}
+
+#[test]
+fn index_matches_linear_scan() {
+    for seed in 0..20 {
+        let mut rng = Lcg::new(seed);
+        let index = MatcherIndex::new(positions(&mut rng, 8));
+        let controllers = controllers(&mut rng, index.positions(), 2_000);
+
+        let (mut num_matched, mut num_partial) = (0, 0);
+        for c in &controllers {
+            let matches = all_matches(index.positions(), c);
+            // Callsigns that only contain a position's callsign, e.g. ZNY_CTR for NY_CTR, which
+            // anchored regexes must not match
+            num_partial += index.positions().iter().any(|p| {
+                c.callsign.contains(&p.position.callsign)
+                    && p.position.simple_callsign() != c.simple_callsign()
+            }) as usize;
+            assert!(
+                matches
+                    .iter()
+                    .flatten()
+                    .all(|p| p.position.simple_callsign() == c.simple_callsign()),
+                "seed {seed}, callsign {}",
+                c.callsign
+            );
+            let expected = ids(matches);
+            assert_eq!(
+                ids(index.all_matches(c)),
+                expected,
+                "seed {seed}, callsign {}, frequency {}",
+                c.callsign,
+                c.frequency
+            );
+            num_matched += usize::from(expected.is_some());
+        }
+
+        // Make sure the fixtures actually exercise both outcomes, and callsigns that only contain
+        // a position's callsign
+        assert!(num_matched > 0 && num_matched < controllers.len());
+        assert!(num_partial > 0, "seed {seed}");
+    }
+}
+
+fn position(callsign: &str) -> Position {
+    Position {
+        callsign: callsign.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn position_regex_matches_whole_callsign_only() {
+    let regex = position("NY_CTR").build_match_regex().unwrap();
+    for callsign in ["NY_CTR", "NY_1_CTR"] {
+        assert!(regex.is_match(callsign), "{callsign}");
+    }
+    for callsign in ["ZNY_CTR", "NY_CTR_1", "ZNY_1_CTR", "NY_CTRL", "NY_12_CTR"] {
+        assert!(!regex.is_match(callsign), "{callsign}");
+    }
+
+    let regex = position("BOS_N_APP").build_match_regex().unwrap();
+    for callsign in ["BOS_N_APP", "BOS_N1_APP"] {
+        assert!(regex.is_match(callsign), "{callsign}");
+    }
+    for callsign in ["XBOS_N_APP", "BOS_N_APP_1", "BOS_APP"] {
+        assert!(!regex.is_match(callsign), "{callsign}");
+    }
+}
@@ -0,0 +1,310 @@
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use shared::{ArchiveConfig, ArchiveRotation};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Error, Lines, Write};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+use vatsim_utils::models::V3ResponseData;
+
+const FILE_PREFIX: &str = "datafeed-";
+const FILE_EXTENSION: &str = ".jsonl.gz";
+
+// One line in an archive file. Every line is written as its own gzip member so that appending to
+// an existing file never requires rewriting it, and a crash can only truncate the last record
+#[derive(Serialize)]
+struct ArchiveRecordRef<'a> {
+    update: DateTime<Utc>,
+    data: &'a V3ResponseData,
+}
+
+#[derive(Deserialize)]
+pub struct ArchiveRecord {
+    pub update: DateTime<Utc>,
+    pub data: V3ResponseData,
+}
+
+pub struct DatafeedArchive {
+    directory: PathBuf,
+    rotation: ArchiveRotation,
+    retention: Option<Duration>,
+    current_file_name: Option<String>,
+}
+
+impl DatafeedArchive {
+    pub fn new(config: &ArchiveConfig) -> Result<Self, Error> {
+        let directory = PathBuf::from(&config.directory);
+        fs::create_dir_all(&directory)?;
+
+        Ok(Self {
+            directory,
+            rotation: config.rotation,
+            retention: config
+                .retention_days
+                .map(|days| Duration::days(i64::from(days))),
+            current_file_name: None,
+        })
+    }
+
+    pub fn write(&mut self, update: DateTime<Utc>, data: &V3ResponseData) -> Result<(), Error> {
+        let file_name = file_name_for(update, self.rotation);
+
+        // Rotated into a new file, so this is the time to clean up anything past retention
+        if self.current_file_name.as_ref() != Some(&file_name) {
+            info!(file_name, "Writing datafeed archive to new file");
+            self.current_file_name = Some(file_name.clone());
+            if let Err(e) = self.prune(update) {
+                warn!(error = ?e, "Could not prune old datafeed archive files");
+            }
+        }
+
+        let mut line = serde_json::to_vec(&ArchiveRecordRef { update, data })?;
+        line.push(b'\n');
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.directory.join(&file_name))?;
+        let mut e = GzEncoder::new(file, Compression::default());
+        e.write_all(&line)?;
+        e.finish()?.sync_data()
+    }
+
+    fn prune(&self, now: DateTime<Utc>) -> Result<(), Error> {
+        let Some(retention) = self.retention else {
+            return Ok(());
+        };
+
+        for entry in fs::read_dir(&self.directory)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().to_string();
+            if let Some(period_start) = period_start_from_file_name(&name) {
+                if now - period_start > retention {
+                    debug!(file_name = name, "Removing expired datafeed archive file");
+                    fs::remove_file(entry.path())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+pub fn file_name_for(update: DateTime<Utc>, rotation: ArchiveRotation) -> String {
+    let period = match rotation {
+        ArchiveRotation::Hourly => update.format("%Y-%m-%dT%H"),
+        ArchiveRotation::Daily => update.format("%Y-%m-%d"),
+    };
+    format!("{FILE_PREFIX}{period}{FILE_EXTENSION}")
+}
+
+pub fn period_start_from_file_name(name: &str) -> Option<DateTime<Utc>> {
+    let period = name
+        .strip_prefix(FILE_PREFIX)?
+        .strip_suffix(FILE_EXTENSION)?;
+
+    // Hourly files carry the hour after a "T", daily files are just the date
+    let with_time = if period.contains('T') {
+        format!("{period}:00:00")
+    } else {
+        format!("{period}T00:00:00")
+    };
+
+    NaiveDateTime::parse_from_str(&with_time, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .map(|d| d.and_utc())
+}
+
+// All archive files in `directory` that may hold snapshots between `from` and `to`, oldest first
+pub fn archive_files_between(
+    directory: &Path,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+) -> Result<Vec<PathBuf>, Error> {
+    let mut files: Vec<(DateTime<Utc>, PathBuf)> = fs::read_dir(directory)?
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            period_start_from_file_name(&name).map(|start| (start, entry.path()))
+        })
+        .collect();
+    files.sort_by_key(|(start, _)| *start);
+
+    // A file can only be skipped for `from` once the next file also starts before it, because the
+    // period length is not known from the name alone
+    let next_starts: Vec<Option<DateTime<Utc>>> = files
+        .iter()
+        .skip(1)
+        .map(|(start, _)| Some(*start))
+        .chain([None])
+        .collect();
+
+    Ok(files
+        .into_iter()
+        .zip(next_starts)
+        .filter(|((start, _), next_start)| {
+            to.is_none_or(|to| *start <= to)
+                && from.is_none_or(|from| next_start.is_none_or(|next| next > from))
+        })
+        .map(|((_, path), _)| path)
+        .collect())
+}
+
+// Streams records out of a single archive file without loading the whole file into memory
+pub struct ArchiveFileReader {
+    lines: Lines<BufReader<MultiGzDecoder<File>>>,
+}
+
+impl ArchiveFileReader {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        Ok(Self {
+            lines: BufReader::new(MultiGzDecoder::new(file)).lines(),
+        })
+    }
+}
+
+impl Iterator for ArchiveFileReader {
+    type Item = Result<ArchiveRecord, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e)),
+        };
+        Some(serde_json::from_str(&line).map_err(Error::from))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().to_utc()
+    }
+
+    // A fresh directory holding empty archive files with the given names
+    fn archive_dir(test: &str, names: &[&str]) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("archive-{test}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        for name in names {
+            File::create(directory.join(name)).unwrap();
+        }
+        directory
+    }
+
+    fn names(files: Vec<PathBuf>) -> Vec<String> {
+        files
+            .iter()
+            .map(|f| f.file_name().unwrap().to_string_lossy().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn file_names_round_trip_to_period_start() {
+        let update = at("2024-07-21T13:45:12Z");
+
+        let hourly = file_name_for(update, ArchiveRotation::Hourly);
+        assert_eq!(hourly, "datafeed-2024-07-21T13.jsonl.gz");
+        assert_eq!(
+            period_start_from_file_name(&hourly),
+            Some(at("2024-07-21T13:00:00Z"))
+        );
+
+        let daily = file_name_for(update, ArchiveRotation::Daily);
+        assert_eq!(daily, "datafeed-2024-07-21.jsonl.gz");
+        assert_eq!(
+            period_start_from_file_name(&daily),
+            Some(at("2024-07-21T00:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn other_file_names_are_ignored() {
+        assert_eq!(
+            period_start_from_file_name("datafeed-2024-07-21T13.jsonl"),
+            None
+        );
+        assert_eq!(
+            period_start_from_file_name("other-2024-07-21.jsonl.gz"),
+            None
+        );
+        assert_eq!(
+            period_start_from_file_name("datafeed-yesterday.jsonl.gz"),
+            None
+        );
+    }
+
+    #[test]
+    fn files_are_selected_around_range_boundaries() {
+        let directory = archive_dir(
+            "boundaries",
+            &[
+                "datafeed-2024-07-21T12.jsonl.gz",
+                "datafeed-2024-07-21T10.jsonl.gz",
+                "datafeed-2024-07-21T11.jsonl.gz",
+                "datafeed-2024-07-21T13.jsonl.gz",
+                "notes.txt",
+            ],
+        );
+
+        // Everything, oldest first
+        assert_eq!(
+            names(archive_files_between(&directory, None, None).unwrap()),
+            [
+                "datafeed-2024-07-21T10.jsonl.gz",
+                "datafeed-2024-07-21T11.jsonl.gz",
+                "datafeed-2024-07-21T12.jsonl.gz",
+                "datafeed-2024-07-21T13.jsonl.gz",
+            ]
+        );
+
+        // The file the range starts in is kept, although its period started before `from`, and
+        // the file starting after `to` is left out
+        assert_eq!(
+            names(
+                archive_files_between(
+                    &directory,
+                    Some(at("2024-07-21T11:30:00Z")),
+                    Some(at("2024-07-21T12:15:00Z"))
+                )
+                .unwrap()
+            ),
+            [
+                "datafeed-2024-07-21T11.jsonl.gz",
+                "datafeed-2024-07-21T12.jsonl.gz",
+            ]
+        );
+
+        // Bounds exactly on period starts
+        assert_eq!(
+            names(
+                archive_files_between(
+                    &directory,
+                    Some(at("2024-07-21T11:00:00Z")),
+                    Some(at("2024-07-21T12:00:00Z"))
+                )
+                .unwrap()
+            ),
+            [
+                "datafeed-2024-07-21T11.jsonl.gz",
+                "datafeed-2024-07-21T12.jsonl.gz",
+            ]
+        );
+
+        // The last file may run on past `from`, as its period length isn't known
+        assert_eq!(
+            names(
+                archive_files_between(&directory, Some(at("2024-07-22T08:00:00Z")), None).unwrap()
+            ),
+            ["datafeed-2024-07-21T13.jsonl.gz"]
+        );
+
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
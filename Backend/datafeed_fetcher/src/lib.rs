@@ -0,0 +1,174 @@
+use crate::archive::DatafeedArchive;
+use crate::source::{DatafeedSource, SourceError};
+use crate::telemetry::{
+    describe_metrics, fetch_error_kind, DUPLICATE_UPDATES, FETCH_DURATION, FETCH_FAILURES,
+    LAST_SENT_UPDATE, LOOP_OVERRUNS, MESSAGES_SENT, PAYLOAD_BYTES, QUEUE_TRIMMED, SEND_FAILURES,
+};
+use chrono::{DateTime, Utc};
+use metrics::{counter, gauge, histogram};
+use shared::envelope::{seal, EnvelopeError, MessageCodec};
+use shared::retry::retry_with_backoff;
+use shared::shutdown::Shutdown;
+use shared::transport::DatafeedTransport;
+use shared::{Config, RedisControllersMsg};
+use std::cmp::min;
+use std::time::{Duration, Instant};
+use tracing::{debug, info, warn};
+use vatsim_utils::models::V3ResponseData;
+
+pub mod archive;
+pub mod replay;
+pub mod source;
+mod telemetry;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error("could not open datafeed archive")]
+    Archive(#[from] std::io::Error),
+
+    #[error("could not initialize datafeed source")]
+    Source(#[from] SourceError),
+}
+
+// Runs until shutdown is requested. A snapshot that is already being sent when that happens is
+// finished first, so it is neither lost nor sent twice after a restart
+pub async fn run_fetcher(
+    config: &Config,
+    mut transport: Box<dyn DatafeedTransport>,
+    mut shutdown: Shutdown,
+) -> Result<(), InitError> {
+    describe_metrics();
+
+    // Set up on-disk archive of raw datafeed snapshots, if configured
+    let mut archive = config
+        .archive
+        .as_ref()
+        .map(DatafeedArchive::new)
+        .transpose()?;
+
+    // Set up VATSIM Datafeed
+    let mut last_datafeed_update = String::new();
+    let Some(mut source) = retry_with_backoff("initialize datafeed source", &mut shutdown, || {
+        DatafeedSource::new(&config.datafeed)
+    })
+    .await?
+    else {
+        return Ok(());
+    };
+
+    // Datafetcher loop
+    while !shutdown.is_requested() {
+        let start = Instant::now();
+
+        // Get data and check that there was no error
+        let url = source.current_url().to_string();
+        let latest_data_result = source.fetch().await;
+        histogram!(FETCH_DURATION).record(start.elapsed().as_secs_f64());
+        if let Err(e) = latest_data_result {
+            warn!(error = ?e, url, "Could not fetch VATSIM data");
+            counter!(FETCH_FAILURES, "error" => fetch_error_kind(&e)).increment(1);
+            shutdown.sleep(Duration::from_secs(1)).await;
+            continue;
+        };
+
+        // Unwrap and check if duplicate from last fetch
+        // Safe to unwrap because checked Err case above already
+        let latest_data = latest_data_result.expect("Error getting VATSIM API data");
+
+        if latest_data.general.update == last_datafeed_update {
+            debug!(time = %latest_data.general.update, "Found duplicate");
+            counter!(DUPLICATE_UPDATES).increment(1);
+            shutdown.sleep(Duration::from_secs(3)).await;
+            continue;
+        }
+
+        // Update timestamp of latest data and process datafeed
+        last_datafeed_update = latest_data.general.update.clone();
+
+        let update_timestamp = if let Ok(update_timestamp) =
+            DateTime::parse_from_rfc3339(&latest_data.general.update_timestamp)
+        {
+            update_timestamp.to_utc()
+        } else {
+            warn!(
+                timestamp = latest_data.general.update_timestamp,
+                "Could not parse timestamp"
+            );
+            counter!(FETCH_FAILURES, "error" => "timestamp").increment(1);
+            continue;
+        };
+
+        // Archive the full snapshot before anything is dropped from it. Failing to archive should
+        // never hold up publishing to the queue
+        if let Some(archive) = archive.as_mut() {
+            if let Err(e) = archive.write(update_timestamp, &latest_data) {
+                warn!(error = ?e, "Could not write datafeed snapshot to archive");
+            }
+        }
+
+        let encoded = match encode(update_timestamp, latest_data, config.codec) {
+            Ok(encoded) => encoded,
+            Err(e) => {
+                warn!(error = ?e, "Could not encode message");
+                continue;
+            }
+        };
+
+        gauge!(PAYLOAD_BYTES).set(encoded.len() as f64);
+
+        // Send message to the queue with Controllers, Pilots, ATIS and General JSON
+        if let Err(e) = transport.send(encoded).await {
+            warn!(error = ?e, "Could not send message to datafeed queue");
+            counter!(SEND_FAILURES).increment(1);
+            // No continue here because at this point we want to sleep for 5 seconds
+        } else {
+            counter!(MESSAGES_SENT).increment(1);
+            gauge!(LAST_SENT_UPDATE).set(update_timestamp.timestamp() as f64);
+        }
+
+        // Keep the queue from growing without bound while no processor is consuming it
+        if let Some(max_depth) = config.queue.max_depth {
+            match transport.trim(max_depth).await {
+                Ok(0) => {}
+                Ok(num_dropped) => {
+                    warn!(
+                        num_dropped,
+                        max_depth, "Dropped oldest messages from datafeed queue"
+                    );
+                    counter!(QUEUE_TRIMMED).increment(num_dropped as u64);
+                }
+                Err(e) => warn!(error = ?e, "Could not trim datafeed queue"),
+            }
+        }
+
+        // Sleep for 5 seconds minus the time this loop took, with some protections to make sure we
+        // don't have a negative duration
+        let loop_time = Instant::now() - start;
+        if loop_time > Duration::from_secs(4) {
+            warn!(?loop_time, "Long loop");
+            counter!(LOOP_OVERRUNS).increment(1);
+        }
+        let sleep_duration = Duration::from_secs(5) - min(Duration::from_secs(4), loop_time);
+        debug!(?sleep_duration, "Sleeping");
+        shutdown.sleep(sleep_duration).await;
+    }
+
+    info!("Fetcher stopped");
+    Ok(())
+}
+
+pub fn encode(
+    update: DateTime<Utc>,
+    data: V3ResponseData,
+    codec: MessageCodec,
+) -> Result<Vec<u8>, EnvelopeError> {
+    let msg = RedisControllersMsg {
+        update,
+        controllers: data.controllers,
+        pilots: data.pilots,
+        atis: data.atis,
+        general: Some(data.general),
+    };
+
+    seal(&msg, codec)
+}
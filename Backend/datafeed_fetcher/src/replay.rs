@@ -0,0 +1,96 @@
+use crate::archive::{archive_files_between, ArchiveFileReader};
+use crate::encode;
+use chrono::{DateTime, Utc};
+use clap::Args;
+use shared::envelope::MessageCodec;
+use shared::transport::DatafeedTransport;
+use std::io::Error;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::time::{sleep_until, Instant};
+use tracing::{info, warn};
+
+#[derive(Debug, Args)]
+pub struct ReplayArgs {
+    /// Directory of archived snapshots. Defaults to `archive.directory` from the configuration
+    #[arg(long)]
+    pub archive_dir: Option<PathBuf>,
+
+    /// Only replay snapshots updated at or after this time (RFC 3339)
+    #[arg(long)]
+    pub from: Option<DateTime<Utc>>,
+
+    /// Only replay snapshots updated at or before this time (RFC 3339)
+    #[arg(long)]
+    pub to: Option<DateTime<Utc>>,
+
+    /// Pacing relative to the recording, e.g. 1 for real-time or 60 to replay an hour per minute
+    #[arg(long, default_value_t = 1.0)]
+    pub speed: f64,
+
+    /// Publish snapshots back to back without any pacing
+    #[arg(long, conflicts_with = "speed")]
+    pub as_fast_as_possible: bool,
+}
+
+pub async fn replay(
+    args: &ReplayArgs,
+    directory: &Path,
+    codec: MessageCodec,
+    transport: &mut dyn DatafeedTransport,
+) -> Result<(), Error> {
+    let valid_speed = args.speed.is_finite() && args.speed > 0.0;
+    if !args.as_fast_as_possible && !valid_speed {
+        return Err(Error::other("Replay speed must be a positive number"));
+    }
+
+    let files = archive_files_between(directory, args.from, args.to)?;
+    info!(num_files = files.len(), ?directory, "Starting replay");
+
+    // Pacing is measured from the first published snapshot so that sleep overshoot doesn't drift
+    let mut pacing_origin: Option<(Instant, DateTime<Utc>)> = None;
+    let mut num_published = 0;
+
+    for file in files {
+        for record in ArchiveFileReader::open(&file)? {
+            // A truncated record can only be the last one in a file, so move on to the next file
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    warn!(error = ?e, ?file, "Could not read archived snapshot, skipping rest of file");
+                    break;
+                }
+            };
+
+            if args.from.is_some_and(|from| record.update < from) {
+                continue;
+            }
+            if args.to.is_some_and(|to| record.update > to) {
+                info!(num_published, "Reached end of replay range");
+                return Ok(());
+            }
+
+            if !args.as_fast_as_possible {
+                match pacing_origin {
+                    Some((started, first_update)) => {
+                        let recorded_offset = (record.update - first_update)
+                            .to_std()
+                            .unwrap_or(Duration::ZERO);
+                        sleep_until(started + recorded_offset.div_f64(args.speed)).await;
+                    }
+                    None => pacing_origin = Some((Instant::now(), record.update)),
+                }
+            }
+
+            let encoded = encode(record.update, record.data, codec).map_err(Error::other)?;
+            if let Err(e) = transport.send(encoded).await {
+                warn!(error = ?e, update = %record.update, "Could not send replayed message to datafeed queue");
+                continue;
+            }
+            num_published += 1;
+        }
+    }
+
+    info!(num_published, "Finished replaying archive");
+    Ok(())
+}
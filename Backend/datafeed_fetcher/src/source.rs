@@ -0,0 +1,136 @@
+use reqwest::{Client, StatusCode};
+use shared::retry::Transient;
+use shared::DatafeedSourceConfig;
+use std::time::Duration;
+use tracing::{info, warn};
+use vatsim_utils::models::{Status, V3ResponseData};
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const USER_AGENT: &str = "github.com/kengreim/IronMic";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SourceError {
+    #[error("HTTP request failed")]
+    Http(#[from] reqwest::Error),
+
+    #[error("invalid HTTP status code {0}")]
+    InvalidStatusCode(StatusCode),
+
+    #[error("status endpoint listed no v3 datafeed URLs")]
+    NoMirrors,
+}
+
+// A status code other than a server error or rate limiting means the URL itself is wrong, and so
+// does a request that couldn't even be built
+impl Transient for SourceError {
+    fn is_transient(&self) -> bool {
+        match self {
+            SourceError::Http(e) => !e.is_builder(),
+            SourceError::InvalidStatusCode(status) => {
+                status.is_server_error() || *status == StatusCode::TOO_MANY_REQUESTS
+            }
+            SourceError::NoMirrors => true,
+        }
+    }
+}
+
+// Fetches the v3 datafeed from the configured source, moving on to the next mirror whenever a
+// fetch fails. Once every mirror has failed in a row, the mirror list is refreshed from the status
+// endpoint in case it has changed
+pub struct DatafeedSource {
+    client: Client,
+    status_url: Option<String>,
+    mirrors: Vec<String>,
+    current: usize,
+    consecutive_failures: usize,
+}
+
+impl DatafeedSource {
+    pub async fn new(config: &DatafeedSourceConfig) -> Result<Self, SourceError> {
+        let client = Client::builder()
+            .user_agent(USER_AGENT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()?;
+
+        let (status_url, mirrors) = match config {
+            DatafeedSourceConfig::Status(url) => {
+                (Some(url.clone()), get_mirrors(&client, url).await?)
+            }
+            DatafeedSourceConfig::Fixed(url) => (None, vec![url.clone()]),
+        };
+        info!(?status_url, ?mirrors, "Initialized datafeed source");
+
+        Ok(Self {
+            client,
+            status_url,
+            mirrors,
+            current: 0,
+            consecutive_failures: 0,
+        })
+    }
+
+    pub fn current_url(&self) -> &str {
+        &self.mirrors[self.current]
+    }
+
+    pub async fn fetch(&mut self) -> Result<V3ResponseData, SourceError> {
+        match get_v3_data(&self.client, self.current_url()).await {
+            Ok(data) => {
+                self.consecutive_failures = 0;
+                Ok(data)
+            }
+            Err(e) => {
+                self.record_failure().await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn record_failure(&mut self) {
+        self.consecutive_failures += 1;
+        self.current = (self.current + 1) % self.mirrors.len();
+
+        if self.consecutive_failures < self.mirrors.len() {
+            return;
+        }
+        self.consecutive_failures = 0;
+
+        // Keep the mirrors we have if the status endpoint is also unavailable
+        if let Some(status_url) = &self.status_url {
+            match get_mirrors(&self.client, status_url).await {
+                Ok(mirrors) => {
+                    info!(?mirrors, "Refreshed datafeed mirrors");
+                    self.mirrors = mirrors;
+                    self.current = 0;
+                }
+                Err(e) => warn!(error = ?e, "Could not refresh datafeed mirrors"),
+            }
+        }
+    }
+}
+
+async fn get_mirrors(client: &Client, status_url: &str) -> Result<Vec<String>, SourceError> {
+    let response = client.get(status_url).send().await?;
+    if !response.status().is_success() {
+        return Err(SourceError::InvalidStatusCode(response.status()));
+    }
+
+    let mirrors = response.json::<Status>().await?.data.v3;
+    if mirrors.is_empty() {
+        return Err(SourceError::NoMirrors);
+    }
+    Ok(mirrors)
+}
+
+async fn get_v3_data(client: &Client, url: &str) -> Result<V3ResponseData, SourceError> {
+    let response = client.get(url).send().await?;
+    if !response.status().is_success() {
+        return Err(SourceError::InvalidStatusCode(response.status()));
+    }
+
+    // Sorted by callsign the same way as the vatsim_utils client, which the fetcher used before
+    let mut data: V3ResponseData = response.json().await?;
+    data.pilots.sort_by(|a, b| a.callsign.cmp(&b.callsign));
+    data.controllers.sort_by(|a, b| a.callsign.cmp(&b.callsign));
+    Ok(data)
+}
@@ -0,0 +1,61 @@
+use crate::source::SourceError;
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+
+pub const FETCH_DURATION: &str = "datafeed_fetch_duration_seconds";
+pub const FETCH_FAILURES: &str = "datafeed_fetch_failures_total";
+pub const DUPLICATE_UPDATES: &str = "datafeed_duplicate_updates_total";
+pub const LOOP_OVERRUNS: &str = "datafeed_loop_overruns_total";
+pub const PAYLOAD_BYTES: &str = "datafeed_payload_bytes";
+pub const SEND_FAILURES: &str = "datafeed_send_failures_total";
+pub const MESSAGES_SENT: &str = "datafeed_messages_sent_total";
+pub const LAST_SENT_UPDATE: &str = "datafeed_last_sent_update_timestamp_seconds";
+pub const QUEUE_TRIMMED: &str = "datafeed_queue_trimmed_messages_total";
+
+pub fn describe_metrics() {
+    describe_histogram!(
+        FETCH_DURATION,
+        Unit::Seconds,
+        "Time taken to fetch the VATSIM v3 datafeed, including failed fetches"
+    );
+    describe_counter!(
+        FETCH_FAILURES,
+        "Datafeed fetches that could not be used, labelled by error type"
+    );
+    describe_counter!(
+        DUPLICATE_UPDATES,
+        "Fetches that returned the same update as the previous one"
+    );
+    describe_counter!(
+        LOOP_OVERRUNS,
+        "Fetch loop iterations that took longer than the loop interval"
+    );
+    describe_gauge!(
+        PAYLOAD_BYTES,
+        Unit::Bytes,
+        "Size of the last encoded message sent to the datafeed queue"
+    );
+    describe_counter!(
+        SEND_FAILURES,
+        "Messages that could not be sent to the datafeed queue"
+    );
+    describe_counter!(MESSAGES_SENT, "Messages sent to the datafeed queue");
+    describe_gauge!(
+        LAST_SENT_UPDATE,
+        Unit::Seconds,
+        "Datafeed update timestamp of the last message sent to the queue"
+    );
+    describe_counter!(
+        QUEUE_TRIMMED,
+        "Oldest messages dropped from the datafeed queue to keep it under its maximum depth"
+    );
+}
+
+pub fn fetch_error_kind(e: &SourceError) -> &'static str {
+    match e {
+        SourceError::InvalidStatusCode(_) => "status_code",
+        SourceError::Http(e) if e.is_timeout() => "timeout",
+        SourceError::Http(e) if e.is_decode() => "decode",
+        SourceError::Http(_) => "http",
+        SourceError::NoMirrors => "no_url",
+    }
+}
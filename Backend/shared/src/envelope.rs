@@ -0,0 +1,222 @@
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+
+// Every message put on the datafeed queue starts with this header:
+//
+//   4 bytes   magic, b"IMDF"
+//   2 bytes   schema version of the message struct, big endian
+//   1 byte    codec id of the payload that follows
+//
+// Schema version 1 is the original format, which has no header at all and is always deflated JSON
+const MAGIC: &[u8; 4] = b"IMDF";
+const HEADER_LEN: usize = MAGIC.len() + 3;
+
+pub const LEGACY_SCHEMA_VERSION: u16 = 1;
+pub const CURRENT_SCHEMA_VERSION: u16 = 3;
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageCodec {
+    #[default]
+    DeflateJson,
+    ZstdJson,
+    MessagePack,
+}
+
+impl MessageCodec {
+    fn id(self) -> u8 {
+        match self {
+            MessageCodec::DeflateJson => 1,
+            MessageCodec::ZstdJson => 2,
+            MessageCodec::MessagePack => 3,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self, EnvelopeError> {
+        match id {
+            1 => Ok(MessageCodec::DeflateJson),
+            2 => Ok(MessageCodec::ZstdJson),
+            3 => Ok(MessageCodec::MessagePack),
+            other => Err(EnvelopeError::UnknownCodec(other)),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EnvelopeError {
+    #[error("could not compress or decompress payload")]
+    Io(#[from] std::io::Error),
+
+    #[error("could not serialize/deserialize JSON payload")]
+    Json(#[from] serde_json::Error),
+
+    #[error("could not serialize MessagePack payload")]
+    MessagePackEncode(#[from] rmp_serde::encode::Error),
+
+    #[error("could not deserialize MessagePack payload")]
+    MessagePackDecode(#[from] rmp_serde::decode::Error),
+
+    #[error("unknown codec id {0}")]
+    UnknownCodec(u8),
+
+    #[error("envelope header is truncated")]
+    TruncatedHeader,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EnvelopeHeader {
+    pub schema_version: u16,
+    pub codec: MessageCodec,
+}
+
+// Serializes a message at the current schema version and wraps it in an envelope
+pub fn seal<T: Serialize>(msg: &T, codec: MessageCodec) -> Result<Vec<u8>, EnvelopeError> {
+    let mut out = Vec::with_capacity(HEADER_LEN);
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&CURRENT_SCHEMA_VERSION.to_be_bytes());
+    out.push(codec.id());
+
+    match codec {
+        MessageCodec::DeflateJson => {
+            let mut e = DeflateEncoder::new(out, Compression::default());
+            serde_json::to_writer(&mut e, msg)?;
+            Ok(e.finish()?)
+        }
+        MessageCodec::ZstdJson => {
+            let mut e = zstd::Encoder::new(out, 0)?;
+            serde_json::to_writer(&mut e, msg)?;
+            Ok(e.finish()?)
+        }
+        MessageCodec::MessagePack => {
+            rmp_serde::encode::write_named(&mut out, msg)?;
+            Ok(out)
+        }
+    }
+}
+
+// Splits a raw queue message into its header and still-encoded payload. Messages without the
+// magic bytes are treated as the legacy, header-less format
+pub fn open(bytes: &[u8]) -> Result<(EnvelopeHeader, &[u8]), EnvelopeError> {
+    if !bytes.starts_with(MAGIC) {
+        let header = EnvelopeHeader {
+            schema_version: LEGACY_SCHEMA_VERSION,
+            codec: MessageCodec::DeflateJson,
+        };
+        return Ok((header, bytes));
+    }
+
+    if bytes.len() < HEADER_LEN {
+        return Err(EnvelopeError::TruncatedHeader);
+    }
+
+    let header = EnvelopeHeader {
+        schema_version: u16::from_be_bytes([bytes[MAGIC.len()], bytes[MAGIC.len() + 1]]),
+        codec: MessageCodec::from_id(bytes[MAGIC.len() + 2])?,
+    };
+    Ok((header, &bytes[HEADER_LEN..]))
+}
+
+pub fn decode_payload<T: DeserializeOwned>(
+    codec: MessageCodec,
+    payload: &[u8],
+) -> Result<T, EnvelopeError> {
+    match codec {
+        MessageCodec::DeflateJson => {
+            let mut s = String::new();
+            DeflateDecoder::new(payload).read_to_string(&mut s)?;
+            Ok(serde_json::from_str(&s)?)
+        }
+        MessageCodec::ZstdJson => Ok(serde_json::from_slice(&zstd::decode_all(payload)?)?),
+        MessageCodec::MessagePack => Ok(rmp_serde::from_slice(payload)?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[derive(Debug, Serialize, Deserialize, PartialEq)]
+    struct Message {
+        update: String,
+        callsigns: Vec<String>,
+        general: Option<u32>,
+    }
+
+    fn message() -> Message {
+        Message {
+            update: "2024-07-21T13:45:12Z".to_string(),
+            callsigns: vec!["BOS_1_CTR".to_string(), "JFK_TWR".to_string()],
+            general: None,
+        }
+    }
+
+    fn round_trip(codec: MessageCodec) {
+        let bytes = seal(&message(), codec).unwrap();
+        let (header, payload) = open(&bytes).unwrap();
+        assert_eq!(
+            header,
+            EnvelopeHeader {
+                schema_version: CURRENT_SCHEMA_VERSION,
+                codec,
+            }
+        );
+        assert_eq!(
+            decode_payload::<Message>(codec, payload).unwrap(),
+            message()
+        );
+    }
+
+    #[test]
+    fn deflate_json_round_trips() {
+        round_trip(MessageCodec::DeflateJson);
+    }
+
+    #[test]
+    fn zstd_json_round_trips() {
+        round_trip(MessageCodec::ZstdJson);
+    }
+
+    #[test]
+    fn message_pack_round_trips() {
+        round_trip(MessageCodec::MessagePack);
+    }
+
+    #[test]
+    fn legacy_payload_without_header_is_deflated_json() {
+        let mut e = DeflateEncoder::new(vec![], Compression::default());
+        e.write_all(&serde_json::to_vec(&message()).unwrap())
+            .unwrap();
+        let bytes = e.finish().unwrap();
+
+        let (header, payload) = open(&bytes).unwrap();
+        assert_eq!(
+            header,
+            EnvelopeHeader {
+                schema_version: LEGACY_SCHEMA_VERSION,
+                codec: MessageCodec::DeflateJson,
+            }
+        );
+        assert_eq!(payload, bytes.as_slice());
+        assert_eq!(
+            decode_payload::<Message>(header.codec, payload).unwrap(),
+            message()
+        );
+    }
+
+    #[test]
+    fn broken_headers_are_rejected() {
+        assert!(matches!(
+            open(b"IMDF\x00"),
+            Err(EnvelopeError::TruncatedHeader)
+        ));
+        assert!(matches!(
+            open(b"IMDF\x00\x03\x09payload"),
+            Err(EnvelopeError::UnknownCodec(9))
+        ));
+    }
+}
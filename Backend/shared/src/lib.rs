@@ -1,10 +1,25 @@
+use crate::envelope::MessageCodec;
 use chrono::{DateTime, Utc};
+use figment::providers::{Env, Format, Toml};
+use figment::Figment;
 use serde::{Deserialize, Serialize};
-use vatsim_utils::models::Controller;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use vatsim_utils::models::{Atis, Controller, GeneralData, Pilot};
+
+pub mod envelope;
+pub mod retry;
+pub mod shutdown;
+pub mod telemetry;
+pub mod transport;
 
 pub const DATAFEED_QUEUE_NAME: &str = "vatsim_datafeed";
+const DEFAULT_CONSUMER_GROUP: &str = "data_processor";
+const DEFAULT_CONSUMER_NAME: &str = "data_processor";
+pub const VATSIM_STATUS_URL: &str = "https://status.vatsim.net/status.json";
 
 #[derive(Debug, Deserialize)]
+#[serde(default)]
 pub struct RedisConfig {
     pub host: String,
     pub port: u16,
@@ -13,6 +28,47 @@ pub struct RedisConfig {
     pub password: Option<String>,
     pub namespace: String,
     pub force_recreate: bool,
+    pub consumer_group: Option<String>,
+    pub consumer_name: Option<String>,
+}
+
+impl Default for RedisConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 6379,
+            db: 0,
+            username: None,
+            password: None,
+            namespace: "rsmq".to_string(),
+            force_recreate: false,
+            consumer_group: None,
+            consumer_name: None,
+        }
+    }
+}
+
+impl RedisConfig {
+    pub fn consumer_group(&self) -> &str {
+        self.consumer_group
+            .as_deref()
+            .unwrap_or(DEFAULT_CONSUMER_GROUP)
+    }
+
+    pub fn consumer_name(&self) -> &str {
+        self.consumer_name
+            .as_deref()
+            .unwrap_or(DEFAULT_CONSUMER_NAME)
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum TransportKind {
+    #[default]
+    Rsmq,
+    RedisStreams,
+    InMemory,
 }
 
 #[derive(Debug, Deserialize)]
@@ -20,14 +76,283 @@ pub struct PostgresConfig {
     pub connection_string: String,
 }
 
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ArchiveRotation {
+    #[default]
+    Hourly,
+    Daily,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ArchiveConfig {
+    pub directory: String,
+    #[serde(default)]
+    pub rotation: ArchiveRotation,
+    pub retention_days: Option<u32>,
+}
+
+// Where the fetcher gets the v3 datafeed from. A status URL is polled for the list of v3 mirrors,
+// which are rotated through on failure. A fixed URL is used as-is, e.g. for a local mock server
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum DatafeedSourceConfig {
+    Status(String),
+    Fixed(String),
+}
+
+impl Default for DatafeedSourceConfig {
+    fn default() -> Self {
+        Self::Status(VATSIM_STATUS_URL.to_string())
+    }
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum StalePolicy {
+    // Process stale messages in memory and save them together, in one batch once the queue has
+    // caught up, or before then every `catch_up_batch_size` messages
+    #[default]
+    CatchUp,
+    // Acknowledge stale messages without processing them and record the gap they leave
+    Skip,
+}
+
+// Limits on how far behind the datafeed queue is allowed to get. Both are off by default, which
+// is also what replaying an archive needs
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct QueueConfig {
+    // The fetcher drops the oldest messages beyond this many
+    pub max_depth: Option<usize>,
+    // The processor applies `stale_policy` to messages whose update is older than this
+    pub stale_after_minutes: Option<u32>,
+    pub stale_policy: StalePolicy,
+    pub catch_up_batch_size: usize,
+    // A message that still can't be decoded after this many deliveries is dropped
+    pub max_decode_attempts: u64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            stale_after_minutes: None,
+            stale_policy: StalePolicy::default(),
+            catch_up_batch_size: 240,
+            max_decode_attempts: 5,
+        }
+    }
+}
+
+// How long an ended session cools down, waiting for its controller to reconnect, before it is
+// completed. The most specific setting applies: ARTCC, then facility type, then the default
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct CooldownConfig {
+    pub default_minutes: u32,
+    // Keyed by callsign suffix, e.g. DEL, GND, TWR, APP or CTR
+    pub facility_types: HashMap<String, u32>,
+    // Keyed by ARTCC id, e.g. ZNY
+    pub artccs: HashMap<String, u32>,
+}
+
+impl Default for CooldownConfig {
+    fn default() -> Self {
+        Self {
+            default_minutes: 5,
+            facility_types: HashMap::new(),
+            artccs: HashMap::new(),
+        }
+    }
+}
+
+impl CooldownConfig {
+    pub fn cooldown_for(&self, simple_callsign: &str, artcc: Option<&str>) -> chrono::Duration {
+        let suffix = simple_callsign
+            .rsplit('_')
+            .next()
+            .unwrap_or(simple_callsign);
+        let minutes = artcc
+            .and_then(|artcc| self.artccs.get(artcc))
+            .or_else(|| self.facility_types.get(suffix))
+            .copied()
+            .unwrap_or(self.default_minutes);
+        chrono::Duration::minutes(minutes.into())
+    }
+}
+
+// Holds sessions instead of ending them when the number of vNAS controllers in a tick drops
+// sharply, as happens when the datafeed is briefly truncated
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct GlitchGuardConfig {
+    // Share of the recent average that has to disappear in one tick to count as a glitch. 1.0
+    // turns the guard off
+    pub max_drop_share: f64,
+    // Below this recent average, drops are taken at face value, e.g. during quiet hours
+    pub min_baseline_controllers: u32,
+    // Number of ticks the recent average is taken over
+    pub recent_ticks: usize,
+    // Ticks a drop is held for before the missing sessions are ended anyway
+    pub hold_ticks: u32,
+}
+
+impl Default for GlitchGuardConfig {
+    fn default() -> Self {
+        Self {
+            max_drop_share: 0.5,
+            min_baseline_controllers: 10,
+            recent_ticks: 5,
+            hold_ticks: 3,
+        }
+    }
+}
+
+// What a position session stands for. Simple callsigns drop the infix, so split sectors such as
+// BOS_1_CTR and BOS_2_CTR share one session. vNAS positions keep them apart wherever a controller
+// matches exactly one position, and fall back to the simple callsign otherwise
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionIdentity {
+    #[default]
+    SimpleCallsign,
+    VnasPosition,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct SessionsConfig {
+    // Sessions that only moved on in time are written at most this often. Anything that opens,
+    // ends or resurrects a session is written on the tick it happens
+    pub flush_interval_seconds: u32,
+    pub cooldown: CooldownConfig,
+    pub glitch_guard: GlitchGuardConfig,
+    pub position_identity: PositionIdentity,
+}
+
+impl Default for SessionsConfig {
+    fn default() -> Self {
+        Self {
+            flush_interval_seconds: 60,
+            cooldown: CooldownConfig::default(),
+            glitch_guard: GlitchGuardConfig::default(),
+            position_identity: PositionIdentity::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct VnasConfig {
+    // How old the last successful vNAS data fetch can get before the processor fetches it again.
+    // Checked in the background, independently of datafeed traffic
+    pub refresh_interval_minutes: u32,
+}
+
+impl Default for VnasConfig {
+    fn default() -> Self {
+        Self {
+            refresh_interval_minutes: 24 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MetricsConfig {
+    pub listen: SocketAddr,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub redis: RedisConfig,
     pub postgres: PostgresConfig,
+    #[serde(default)]
+    pub transport: TransportKind,
+    #[serde(default)]
+    pub codec: MessageCodec,
+    #[serde(default)]
+    pub datafeed: DatafeedSourceConfig,
+    #[serde(default)]
+    pub queue: QueueConfig,
+    #[serde(default)]
+    pub sessions: SessionsConfig,
+    #[serde(default)]
+    pub vnas: VnasConfig,
+    pub archive: Option<ArchiveConfig>,
+    pub metrics: Option<MetricsConfig>,
+}
+
+pub fn load_config() -> Result<Config, Box<figment::Error>> {
+    Figment::new()
+        .merge(Toml::file("Settings.toml"))
+        .merge(Env::prefixed("STATUSA_").split("_"))
+        .extract::<Config>()
+        .map_err(Box::new)
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RedisControllersMsg {
     pub update: DateTime<Utc>,
     pub controllers: Vec<Controller>,
+    #[serde(default)]
+    pub pilots: Vec<Pilot>,
+    #[serde(default)]
+    pub atis: Vec<Atis>,
+    #[serde(default)]
+    pub general: Option<GeneralData>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn cooldowns() -> CooldownConfig {
+        CooldownConfig {
+            default_minutes: 5,
+            facility_types: HashMap::from([("CTR".to_string(), 15), ("TWR".to_string(), 8)]),
+            artccs: HashMap::from([("ZNY".to_string(), 20)]),
+        }
+    }
+
+    #[test]
+    fn facility_type_overrides_default() {
+        let config = cooldowns();
+        assert_eq!(config.cooldown_for("BOS_CTR", None), Duration::minutes(15));
+        assert_eq!(
+            config.cooldown_for("BOS_TWR", Some("ZBW")),
+            Duration::minutes(8)
+        );
+    }
+
+    #[test]
+    fn unlisted_facility_type_falls_back_to_default() {
+        let config = cooldowns();
+        assert_eq!(config.cooldown_for("BOS_GND", None), Duration::minutes(5));
+        assert_eq!(
+            config.cooldown_for("BOS_APP", Some("ZBW")),
+            Duration::minutes(5)
+        );
+        // No suffix to go by
+        assert_eq!(config.cooldown_for("BOS", None), Duration::minutes(5));
+        assert_eq!(
+            CooldownConfig::default().cooldown_for("BOS_CTR", Some("ZNY")),
+            Duration::minutes(5)
+        );
+    }
+
+    #[test]
+    fn artcc_takes_precedence_over_facility_type() {
+        let config = cooldowns();
+        assert_eq!(
+            config.cooldown_for("NY_CTR", Some("ZNY")),
+            Duration::minutes(20)
+        );
+        assert_eq!(
+            config.cooldown_for("JFK_GND", Some("ZNY")),
+            Duration::minutes(20)
+        );
+    }
 }
@@ -0,0 +1,49 @@
+use crate::shutdown::Shutdown;
+use std::fmt::Debug;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+const INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// Tells apart failures that may go away on their own, like a server that isn't up yet, from ones
+// that will fail the same way on every attempt, like a bad connection string
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+// Runs `operation` until it succeeds, doubling the wait between attempts up to a minute. Returns
+// the error straight away if it isn't transient, and Ok(None) if shutdown is requested while
+// waiting to retry
+pub async fn retry_with_backoff<T, E, F, Fut>(
+    what: &str,
+    shutdown: &mut Shutdown,
+    mut operation: F,
+) -> Result<Option<T>, E>
+where
+    E: Debug + Transient,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = INITIAL_BACKOFF;
+    let mut attempt = 1;
+
+    loop {
+        match operation().await {
+            Ok(value) => return Ok(Some(value)),
+            Err(e) if !e.is_transient() => return Err(e),
+            Err(e) => {
+                warn!(error = ?e, what, attempt, ?backoff, "Attempt failed, retrying");
+            }
+        }
+
+        shutdown.sleep(backoff).await;
+        if shutdown.is_requested() {
+            return Ok(None);
+        }
+
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        attempt += 1;
+    }
+}
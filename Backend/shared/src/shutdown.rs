@@ -0,0 +1,79 @@
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::sleep;
+use tracing::{info, warn};
+
+// Set once SIGTERM or SIGINT is received. Loops check it between iterations, so whatever they are
+// in the middle of (sending a snapshot, processing a message) is finished before they stop
+#[derive(Clone)]
+pub struct Shutdown {
+    sender: Arc<watch::Sender<bool>>,
+    receiver: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    // Must be called from within the runtime, as the signal handlers are registered on it
+    pub fn listen() -> Self {
+        let (sender, receiver) = watch::channel(false);
+        let shutdown = Self {
+            sender: Arc::new(sender),
+            receiver,
+        };
+
+        let signalled = shutdown.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            info!("Shutdown requested, finishing current work");
+            signalled.request();
+        });
+        shutdown
+    }
+
+    // Stops everything sharing this shutdown as if a signal had been received, for when one loop
+    // fails and the others can't do anything useful without it
+    pub fn request(&self) {
+        self.sender.send_replace(true);
+    }
+
+    pub fn is_requested(&self) -> bool {
+        *self.receiver.borrow()
+    }
+
+    // Resolves once shutdown has been requested, for racing against sleeps and other waits
+    pub async fn requested(&mut self) {
+        let _ = self.receiver.wait_for(|requested| *requested).await;
+    }
+
+    // Sleeps for `duration`, cut short if shutdown is requested in the meantime
+    pub async fn sleep(&mut self, duration: Duration) {
+        tokio::select! {
+            _ = sleep(duration) => {}
+            _ = self.requested() => {}
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = match signal(SignalKind::terminate()) {
+        Ok(terminate) => terminate,
+        Err(e) => {
+            warn!(error = ?e, "Could not listen for SIGTERM, only handling SIGINT");
+            let _ = tokio::signal::ctrl_c().await;
+            return;
+        }
+    };
+
+    tokio::select! {
+        _ = terminate.recv() => {}
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
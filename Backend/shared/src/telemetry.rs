@@ -0,0 +1,111 @@
+use crate::MetricsConfig;
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::Router;
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder, PrometheusHandle};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tracing::{info, warn};
+
+// Bucket bounds in seconds for any histogram whose name ends in `_seconds`
+const DURATION_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 15.0, 30.0];
+const UPKEEP_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Debug, thiserror::Error)]
+pub enum TelemetryError {
+    #[error("could not install metrics recorder")]
+    Recorder(#[from] BuildError),
+
+    #[error("could not bind metrics listener")]
+    Bind(#[from] std::io::Error),
+}
+
+// Named readiness checks shared between the processing loops and the `/readyz` endpoint. The
+// process is ready once every registered check has been marked ready
+#[derive(Clone, Default)]
+pub struct Readiness {
+    checks: Arc<Mutex<BTreeMap<&'static str, bool>>>,
+}
+
+impl Readiness {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&self, check: &'static str) {
+        self.checks
+            .lock()
+            .expect("Readiness lock poisoned")
+            .entry(check)
+            .or_insert(false);
+    }
+
+    pub fn set(&self, check: &'static str, ready: bool) {
+        self.checks
+            .lock()
+            .expect("Readiness lock poisoned")
+            .insert(check, ready);
+    }
+
+    fn report(&self) -> (bool, String) {
+        let checks = self.checks.lock().expect("Readiness lock poisoned");
+        let mut body = String::new();
+        for (check, ready) in checks.iter() {
+            let _ = writeln!(
+                body,
+                "{check}: {}",
+                if *ready { "ready" } else { "not ready" }
+            );
+        }
+        (checks.values().all(|ready| *ready), body)
+    }
+}
+
+// Installs the global Prometheus recorder and serves it over HTTP in the background, along with
+// liveness and readiness endpoints. Until this is called, every metric recorded through the
+// `metrics` macros is a no-op
+pub async fn start_metrics_server(
+    config: &MetricsConfig,
+    readiness: Readiness,
+) -> Result<(), TelemetryError> {
+    let handle = PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), DURATION_BUCKETS)?
+        .install_recorder()?;
+
+    let listener = TcpListener::bind(config.listen).await?;
+    info!(listen = %config.listen, "Serving metrics");
+
+    let upkeep_handle = handle.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(UPKEEP_INTERVAL).await;
+            upkeep_handle.run_upkeep();
+        }
+    });
+
+    let app = Router::new()
+        .route("/metrics", get(move || render(handle.clone())))
+        .route("/healthz", get(|| async { "ok" }))
+        .route("/readyz", get(move || ready(readiness.clone())));
+    tokio::spawn(async move {
+        if let Err(e) = axum::serve(listener, app).await {
+            warn!(error = ?e, "Metrics server stopped");
+        }
+    });
+
+    Ok(())
+}
+
+async fn render(handle: PrometheusHandle) -> String {
+    handle.render()
+}
+
+async fn ready(readiness: Readiness) -> (StatusCode, String) {
+    match readiness.report() {
+        (true, body) => (StatusCode::OK, body),
+        (false, body) => (StatusCode::SERVICE_UNAVAILABLE, body),
+    }
+}
@@ -0,0 +1,94 @@
+use crate::retry::Transient;
+use crate::{RedisConfig, TransportKind};
+use async_trait::async_trait;
+use redis::{ErrorKind, RedisError};
+use rsmq_async::RsmqError;
+
+pub mod in_memory;
+pub mod redis_streams;
+pub mod rsmq;
+
+pub use in_memory::InMemoryTransport;
+pub use redis_streams::RedisStreamsTransport;
+pub use rsmq::RsmqTransport;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TransportError {
+    #[error("error with RSMQ")]
+    Rsmq(#[from] RsmqError),
+
+    #[error("error with Redis")]
+    Redis(#[from] RedisError),
+
+    #[error(
+        "the in-memory transport is only available when fetcher and processor share a process"
+    )]
+    InMemoryUnavailable,
+}
+
+// Only failures to reach Redis are worth retrying. Anything else, like a malformed URL, rejected
+// credentials or a transport that can't be used here, fails the same way every time
+impl Transient for TransportError {
+    fn is_transient(&self) -> bool {
+        match self {
+            TransportError::Rsmq(RsmqError::RedisError(e)) | TransportError::Redis(e) => {
+                is_transient_redis_error(e)
+            }
+            // Pooled connections that couldn't be set up or handed out in time
+            TransportError::Rsmq(RsmqError::RunError(_))
+            | TransportError::Rsmq(RsmqError::NoConnectionAcquired) => true,
+            TransportError::Rsmq(_) | TransportError::InMemoryUnavailable => false,
+        }
+    }
+}
+
+fn is_transient_redis_error(e: &RedisError) -> bool {
+    e.is_io_error()
+        || matches!(
+            e.kind(),
+            ErrorKind::BusyLoadingError
+                | ErrorKind::TryAgain
+                | ErrorKind::ClusterDown
+                | ErrorKind::MasterDown
+        )
+}
+
+#[derive(Debug, Clone)]
+pub struct DatafeedMessage {
+    pub id: String,
+    pub payload: Vec<u8>,
+    // How many times the message has been handed out by `receive`, including this time
+    pub delivery_count: u64,
+}
+
+// Moves compressed datafeed messages from the fetcher to the processor. Messages handed out by
+// `receive` stay owned by the transport until they are acknowledged, so that a processor which
+// dies mid-message can have it redelivered where the transport supports that
+#[async_trait]
+pub trait DatafeedTransport: Send {
+    async fn send(&mut self, payload: Vec<u8>) -> Result<(), TransportError>;
+
+    // Returns immediately with None if there is no message waiting
+    async fn receive(&mut self) -> Result<Option<DatafeedMessage>, TransportError>;
+
+    async fn ack(&mut self, message_id: &str) -> Result<(), TransportError>;
+
+    // Drops the oldest messages until at most `max_len` are queued, returning how many were dropped
+    async fn trim(&mut self, max_len: usize) -> Result<usize, TransportError>;
+}
+
+pub async fn connect_transport(
+    kind: TransportKind,
+    config: &RedisConfig,
+    force_recreate: bool,
+) -> Result<Box<dyn DatafeedTransport>, TransportError> {
+    match kind {
+        TransportKind::Rsmq => Ok(Box::new(
+            RsmqTransport::connect(config, force_recreate).await?,
+        )),
+        TransportKind::RedisStreams => Ok(Box::new(
+            RedisStreamsTransport::connect(config, force_recreate).await?,
+        )),
+        TransportKind::InMemory => Err(TransportError::InMemoryUnavailable),
+    }
+}
@@ -0,0 +1,54 @@
+use super::{DatafeedMessage, DatafeedTransport, TransportError};
+use async_trait::async_trait;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+// Queue shared between clones, for running the fetcher and processor in the same process. There
+// is nothing to redeliver to after a crash, so messages are dropped as soon as they are received
+#[derive(Clone, Default)]
+pub struct InMemoryTransport {
+    queue: Arc<Mutex<VecDeque<DatafeedMessage>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl InMemoryTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+#[async_trait]
+impl DatafeedTransport for InMemoryTransport {
+    async fn send(&mut self, payload: Vec<u8>) -> Result<(), TransportError> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        self.queue
+            .lock()
+            .expect("In-memory queue lock poisoned")
+            .push_back(DatafeedMessage {
+                id,
+                payload,
+                delivery_count: 1,
+            });
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Option<DatafeedMessage>, TransportError> {
+        Ok(self
+            .queue
+            .lock()
+            .expect("In-memory queue lock poisoned")
+            .pop_front())
+    }
+
+    async fn ack(&mut self, _message_id: &str) -> Result<(), TransportError> {
+        Ok(())
+    }
+
+    async fn trim(&mut self, max_len: usize) -> Result<usize, TransportError> {
+        let mut queue = self.queue.lock().expect("In-memory queue lock poisoned");
+        let num_dropped = queue.len().saturating_sub(max_len);
+        queue.drain(..num_dropped);
+        Ok(num_dropped)
+    }
+}
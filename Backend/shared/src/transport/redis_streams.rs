@@ -0,0 +1,145 @@
+use super::{DatafeedMessage, DatafeedTransport, TransportError};
+use crate::{RedisConfig, DATAFEED_QUEUE_NAME};
+use async_trait::async_trait;
+use redis::aio::MultiplexedConnection;
+use redis::streams::{StreamMaxlen, StreamPendingCountReply, StreamReadOptions, StreamReadReply};
+use redis::{AsyncCommands, Client, ConnectionAddr, ConnectionInfo, RedisConnectionInfo};
+
+const PAYLOAD_FIELD: &str = "payload";
+
+pub struct RedisStreamsTransport {
+    connection: MultiplexedConnection,
+    stream_key: String,
+    group: String,
+    consumer: String,
+    // Entries delivered to this consumer before a restart but never acked are read back first,
+    // each one once. The cursor is the last pending id handed out, so an entry that is left
+    // unacked again can't keep the drain from reaching the new entries behind it
+    pending_cursor: Option<String>,
+}
+
+impl RedisStreamsTransport {
+    pub async fn connect(
+        config: &RedisConfig,
+        force_recreate: bool,
+    ) -> Result<Self, TransportError> {
+        let connection_info = ConnectionInfo {
+            addr: ConnectionAddr::Tcp(config.host.to_string(), config.port),
+            redis: RedisConnectionInfo {
+                db: config.db.into(),
+                username: config.username.clone(),
+                password: config.password.clone(),
+            },
+        };
+        let mut connection = Client::open(connection_info)?
+            .get_multiplexed_async_connection()
+            .await?;
+
+        let stream_key = format!("{}:{}", config.namespace, DATAFEED_QUEUE_NAME);
+        let group = config.consumer_group().to_string();
+
+        if force_recreate {
+            connection.del::<_, ()>(&stream_key).await?;
+        }
+
+        // Start the group at the beginning of the stream so that a backlog written before the
+        // first processor started is not skipped. The group already existing is not an error
+        let created: Result<(), _> = connection
+            .xgroup_create_mkstream(&stream_key, &group, "0")
+            .await;
+        if let Err(e) = created {
+            if e.code() != Some("BUSYGROUP") {
+                return Err(e.into());
+            }
+        }
+
+        Ok(Self {
+            connection,
+            stream_key,
+            group,
+            consumer: config.consumer_name().to_string(),
+            pending_cursor: Some("0".to_string()),
+        })
+    }
+
+    async fn read_one(&mut self, from_id: &str) -> Result<Option<DatafeedMessage>, TransportError> {
+        let options = StreamReadOptions::default()
+            .group(&self.group, &self.consumer)
+            .count(1);
+        let reply: Option<StreamReadReply> = self
+            .connection
+            .xread_options(&[&self.stream_key], &[from_id], &options)
+            .await?;
+
+        Ok(reply
+            .and_then(|r| r.keys.into_iter().next())
+            .and_then(|k| k.ids.into_iter().next())
+            .map(|entry| DatafeedMessage {
+                payload: entry.get(PAYLOAD_FIELD).unwrap_or_default(),
+                id: entry.id,
+                delivery_count: 1,
+            }))
+    }
+
+    // Redis counts deliveries of pending entries, including the read that just happened
+    async fn delivery_count(&mut self, message_id: &str) -> Result<u64, TransportError> {
+        let reply: StreamPendingCountReply = self
+            .connection
+            .xpending_count(&self.stream_key, &self.group, message_id, message_id, 1)
+            .await?;
+        Ok(reply.ids.first().map_or(1, |p| p.times_delivered as u64))
+    }
+}
+
+#[async_trait]
+impl DatafeedTransport for RedisStreamsTransport {
+    async fn send(&mut self, payload: Vec<u8>) -> Result<(), TransportError> {
+        self.connection
+            .xadd::<_, _, _, _, ()>(&self.stream_key, "*", &[(PAYLOAD_FIELD, payload)])
+            .await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Option<DatafeedMessage>, TransportError> {
+        while let Some(cursor) = self.pending_cursor.take() {
+            let Some(message) = self.read_one(&cursor).await? else {
+                break;
+            };
+            self.pending_cursor = Some(message.id.clone());
+
+            // A pending entry that was trimmed away comes back without its payload and can only be
+            // acked so it stops being redelivered
+            if message.payload.is_empty() {
+                self.ack(&message.id).await?;
+            } else {
+                let delivery_count = self.delivery_count(&message.id).await?;
+                return Ok(Some(DatafeedMessage {
+                    delivery_count,
+                    ..message
+                }));
+            }
+        }
+
+        self.read_one(">").await
+    }
+
+    async fn ack(&mut self, message_id: &str) -> Result<(), TransportError> {
+        self.connection
+            .xack::<_, _, _, ()>(&self.stream_key, &self.group, &[message_id])
+            .await?;
+        // Acked entries are never read again, so there's no reason to keep them in memory
+        self.connection
+            .xdel::<_, _, ()>(&self.stream_key, &[message_id])
+            .await?;
+        Ok(())
+    }
+
+    // Entries delivered but not yet acked count towards the length and can be trimmed too. The
+    // pending drain in `receive` clears out whatever that leaves behind
+    async fn trim(&mut self, max_len: usize) -> Result<usize, TransportError> {
+        Ok(self
+            .connection
+            .xtrim(&self.stream_key, StreamMaxlen::Equals(max_len))
+            .await?)
+    }
+}
@@ -0,0 +1,91 @@
+use super::{DatafeedMessage, DatafeedTransport, TransportError};
+use crate::{RedisConfig, DATAFEED_QUEUE_NAME};
+use async_trait::async_trait;
+use rsmq_async::{Rsmq, RsmqConnection, RsmqOptions};
+
+pub struct RsmqTransport {
+    rsmq: Rsmq,
+}
+
+impl RsmqTransport {
+    pub async fn connect(
+        config: &RedisConfig,
+        force_recreate: bool,
+    ) -> Result<Self, TransportError> {
+        let connection_options = RsmqOptions {
+            host: config.host.to_string(),
+            port: config.port,
+            db: config.db,
+            realtime: false,
+            username: config.username.clone(),
+            password: config.password.clone(),
+            ns: config.namespace.to_string(),
+        };
+
+        let mut rsmq = Rsmq::new(connection_options).await?;
+        let queues = rsmq.list_queues().await?;
+
+        let queue_exists = queues.contains(&DATAFEED_QUEUE_NAME.to_string());
+        if queue_exists && force_recreate {
+            rsmq.delete_queue(DATAFEED_QUEUE_NAME).await?;
+            rsmq.create_queue(DATAFEED_QUEUE_NAME, None, None, Some(-1))
+                .await?;
+        } else if queue_exists {
+            rsmq.set_queue_attributes(DATAFEED_QUEUE_NAME, None, None, Some(-1))
+                .await?;
+        } else {
+            rsmq.create_queue(DATAFEED_QUEUE_NAME, None, None, Some(-1))
+                .await?
+        }
+
+        Ok(Self { rsmq })
+    }
+}
+
+#[async_trait]
+impl DatafeedTransport for RsmqTransport {
+    async fn send(&mut self, payload: Vec<u8>) -> Result<(), TransportError> {
+        self.rsmq
+            .send_message::<Vec<u8>>(DATAFEED_QUEUE_NAME, payload, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn receive(&mut self) -> Result<Option<DatafeedMessage>, TransportError> {
+        let message = self
+            .rsmq
+            .receive_message::<Vec<u8>>(DATAFEED_QUEUE_NAME, None)
+            .await?;
+        Ok(message.map(|m| DatafeedMessage {
+            id: m.id,
+            payload: m.message,
+            delivery_count: m.rc,
+        }))
+    }
+
+    async fn ack(&mut self, message_id: &str) -> Result<(), TransportError> {
+        self.rsmq
+            .delete_message(DATAFEED_QUEUE_NAME, message_id)
+            .await?;
+        Ok(())
+    }
+
+    async fn trim(&mut self, max_len: usize) -> Result<usize, TransportError> {
+        let attributes = self.rsmq.get_queue_attributes(DATAFEED_QUEUE_NAME).await?;
+        let excess = (attributes.msgs as usize).saturating_sub(max_len);
+
+        // Popping takes the oldest visible message, so messages being processed are left alone
+        let mut num_dropped = 0;
+        for _ in 0..excess {
+            let popped = self
+                .rsmq
+                .pop_message::<Vec<u8>>(DATAFEED_QUEUE_NAME)
+                .await?;
+            if popped.is_none() {
+                break;
+            }
+            num_dropped += 1;
+        }
+        Ok(num_dropped)
+    }
+}